@@ -0,0 +1,70 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 position;
+} camera;
+
+layout(set = 1, binding = 0) uniform Material {
+    vec4 base_color;
+    //w holds the alpha cutoff
+    vec4 emissive;
+    float metallic;
+    float roughness;
+    float normal_scale;
+    float occlusion_strength;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler2D base_color_texture;
+layout(set = 1, binding = 2) uniform sampler2D metallic_roughness_texture;
+layout(set = 1, binding = 3) uniform sampler2D normal_texture;
+layout(set = 1, binding = 4) uniform sampler2D occlusion_texture;
+layout(set = 1, binding = 5) uniform sampler2D emissive_texture;
+
+layout(location = 0) in vec3 i_position;
+layout(location = 1) in vec3 i_normal;
+layout(location = 2) in vec4 i_tangent;
+layout(location = 3) in vec2 i_uv;
+layout(location = 4) in vec4 i_color;
+
+layout(location = 0) out vec4 o_color;
+
+//a fixed sun until scene lights are uploaded
+const vec3 light_direction = normalize(vec3(0.4, 1.0, 0.3));
+const float ambient = 0.1;
+
+void main() {
+    vec4 base_color = material.base_color * i_color * texture(base_color_texture, i_uv);
+    if (base_color.a < material.emissive.w) {
+        discard;
+    }
+
+    vec3 normal = normalize(i_normal);
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    vec3 tangent = normalize(i_tangent.xyz - normal * dot(normal, i_tangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * i_tangent.w;
+    vec3 tangent_normal = texture(normal_texture, i_uv).xyz * 2.0 - 1.0;
+    tangent_normal.xy *= material.normal_scale;
+    normal = normalize(mat3(tangent, bitangent, normal) * tangent_normal);
+
+    vec2 metallic_roughness = texture(metallic_roughness_texture, i_uv).bg * vec2(material.metallic, material.roughness);
+    float occlusion = mix(1.0, texture(occlusion_texture, i_uv).r, material.occlusion_strength);
+
+    vec3 view_direction = normalize(camera.position.xyz - i_position);
+    vec3 half_vector = normalize(light_direction + view_direction);
+    float shininess = 2.0 / max(metallic_roughness.y * metallic_roughness.y, 0.001);
+    float specular = pow(max(dot(normal, half_vector), 0.0), shininess) * (1.0 - metallic_roughness.y);
+
+    vec3 diffuse_color = base_color.rgb * (1.0 - metallic_roughness.x);
+    vec3 specular_color = mix(vec3(0.04), base_color.rgb, metallic_roughness.x);
+    float diffuse = max(dot(normal, light_direction), 0.0);
+
+    vec3 color = diffuse_color * (diffuse + ambient * occlusion) + specular_color * specular;
+    color += material.emissive.rgb * texture(emissive_texture, i_uv).rgb;
+
+    o_color = vec4(color, base_color.a);
+}
@@ -0,0 +1,36 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 position;
+} camera;
+
+layout(push_constant) uniform Model {
+    mat4 model;
+};
+
+layout(location = 0) in vec3 i_position;
+layout(location = 1) in vec3 i_normal;
+layout(location = 2) in vec4 i_tangent;
+layout(location = 3) in vec2 i_uv;
+layout(location = 4) in vec4 i_color;
+
+layout(location = 0) out vec3 o_position;
+layout(location = 1) out vec3 o_normal;
+layout(location = 2) out vec4 o_tangent;
+layout(location = 3) out vec2 o_uv;
+layout(location = 4) out vec4 o_color;
+
+void main() {
+    vec4 world = model * vec4(i_position, 1.0);
+    mat3 normal_matrix = transpose(inverse(mat3(model)));
+
+    gl_Position = camera.view_projection * world;
+    o_position = world.xyz;
+    o_normal = normal_matrix * i_normal;
+    o_tangent = vec4(mat3(model) * i_tangent.xyz, i_tangent.w);
+    o_uv = i_uv;
+    o_color = i_color;
+}
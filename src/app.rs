@@ -0,0 +1,239 @@
+use crate::input::{ActionMap, Input};
+use crate::renderer::{Frame, Renderer, graph::PassContext, debug::ShaderPrintf, config::{LoopConfig, RendererConfig, WindowConfig}};
+
+use std::{cell::RefCell, time::{Duration, Instant}};
+use winit::{event::{Event, WindowEvent}, event_loop::ControlFlow, window::WindowId};
+
+//every method has a default, an app only overrides what it needs
+pub trait App {
+    fn init(&mut self, _ctx: &mut Context) {}
+
+    //once per frame with the time since the last one
+    fn update(&mut self, _ctx: &mut Context, _dt: f32) {}
+
+    //zero or more times per frame with the fixed timestep
+    fn fixed_update(&mut self, _ctx: &mut Context, _dt: f32) {}
+
+    //once per window and frame, recorded inside the main pass with the backbuffer as the color attachment
+    //and a reversed depth buffer cleared to 0,
+    //time.alpha tells how far the frame is between the last two fixed updates
+    fn render(&mut self, _time: &Time, _frame: &Frame, _pass: &PassContext) {}
+
+    //physical size, the swapchain is recreated before the next frame
+    fn on_resize(&mut self, _ctx: &mut Context, _window: WindowId, _width: u32, _height: u32) {}
+
+    //windows asked for with Context::open_window, in the order they were asked for
+    fn on_window_opened(&mut self, _ctx: &mut Context, _window: WindowId) {}
+
+    //called before the window's viewport is removed, closing the primary window exits instead
+    fn on_window_closed(&mut self, _ctx: &mut Context, _window: WindowId) {}
+
+    //every winit event, after the input state saw it
+    fn on_event(&mut self, _ctx: &mut Context, _event: &Event<()>) {}
+
+    //once per frame with the shader printf output received since the last call, oldest first,
+    //only called when there is some
+    fn on_shader_printf(&mut self, _ctx: &mut Context, _output: &[ShaderPrintf]) {}
+}
+
+pub struct Time {
+    //seconds since the last frame
+    pub delta: f32,
+    pub fixed_delta: f32,
+    //between 0 and 1, for interpolating the last two fixed update states
+    pub alpha: f32,
+    pub elapsed: Duration,
+    pub frame: u64,
+    pub fixed_steps: u64
+}
+
+pub struct Context {
+    pub renderer: Renderer,
+    pub input: Input,
+    pub time: Time,
+    pending_windows: Vec<WindowConfig>,
+    exit: bool
+}
+
+impl Context {
+    pub fn exit(&mut self) {
+        self.exit = true;
+    }
+
+    //opened once control returns to the event loop
+    pub fn open_window(&mut self, config: WindowConfig) {
+        self.pending_windows.push(config);
+    }
+}
+
+//tracks real time and how much of it the fixed updates still have to simulate
+struct Clock {
+    start: Instant,
+    last_frame: Instant,
+    next_frame: Instant,
+    accumulator: Duration,
+    fixed_step: Duration,
+    max_fixed_steps: u32,
+    frame_interval: Option<Duration>
+}
+
+impl Clock {
+    fn new(config: &LoopConfig) -> Self {
+        let now = Instant::now();
+
+        Self {
+            start: now,
+            last_frame: now,
+            next_frame: now,
+            accumulator: Duration::ZERO,
+            fixed_step: Duration::from_secs_f64(1.0 / config.fixed_rate),
+            max_fixed_steps: config.max_fixed_steps,
+            frame_interval: config.frame_limit.map(|fps| Duration::from_secs_f64(1.0 / fps))
+        }
+    }
+
+    //when the frame limit wants the next frame to wait
+    fn wait_until(&self, now: Instant) -> Option<Instant> {
+        self.frame_interval?;
+        (now < self.next_frame).then_some(self.next_frame)
+    }
+
+    //returns the frame's delta and how many fixed steps to run
+    fn tick(&mut self, now: Instant) -> (Duration, u32) {
+        let delta = now - self.last_frame;
+        self.last_frame = now;
+
+        if let Some(interval) = self.frame_interval {
+            //paced from the previous deadline so the rate doesn't drift, restarted after stalls
+            self.next_frame = (self.next_frame + interval).max(now);
+        }
+
+        self.accumulator += delta;
+        let mut steps = 0;
+        while self.accumulator >= self.fixed_step && steps < self.max_fixed_steps {
+            self.accumulator -= self.fixed_step;
+            steps += 1;
+        }
+        if self.accumulator >= self.fixed_step {
+            log::warn!("dropping {:?} of fixed updates after a stall", self.accumulator);
+            self.accumulator = Duration::ZERO;
+        }
+
+        (delta, steps)
+    }
+
+    fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.fixed_step.as_secs_f32()
+    }
+}
+
+//only returns when the config is invalid, the process exits with the event loop otherwise
+pub fn run<A: App + 'static>(config: RendererConfig, mut app: A) -> anyhow::Result<()> {
+    //the builders and struct literals skip the checks of the config file and the overrides
+    config.validate()?;
+    let loop_config = config.game_loop.clone();
+
+    let mut renderer = Renderer::new(config)?;
+    let event_loop = renderer.event_loop().unwrap();
+
+    let mut ctx = Context {
+        renderer,
+        input: Input::new(ActionMap::from_env().unwrap()),
+        time: Time {
+            delta: 0.0,
+            fixed_delta: (1.0 / loop_config.fixed_rate) as f32,
+            alpha: 0.0,
+            elapsed: Duration::ZERO,
+            frame: 0,
+            fixed_steps: 0
+        },
+        pending_windows: vec![],
+        exit: false
+    };
+
+    app.init(&mut ctx);
+
+    //started after init so loading doesn't count as a stall
+    let mut clock = Clock::new(&loop_config);
+
+    event_loop.run(move |event, target, control_flow| {
+        ctx.input.handle_event(&event);
+        app.on_event(&mut ctx, &event);
+
+        match event {
+            Event::WindowEvent { window_id, event: WindowEvent::CloseRequested } => {
+                if window_id == ctx.renderer.primary {
+                    ctx.exit();
+                } else {
+                    app.on_window_closed(&mut ctx, window_id);
+                    ctx.renderer.remove_window(window_id);
+                }
+            },
+            Event::WindowEvent { window_id, event: WindowEvent::Resized(size) } => {
+                if let Some(viewport) = ctx.renderer.viewport_mut(window_id) {
+                    viewport.swapchain_outdated = true;
+                }
+                app.on_resize(&mut ctx, window_id, size.width, size.height);
+            },
+            Event::MainEventsCleared => {
+                for config in std::mem::take(&mut ctx.pending_windows) {
+                    match ctx.renderer.add_window(target, &config) {
+                        Ok(window) => app.on_window_opened(&mut ctx, window),
+                        Err(error) => log::error!("failed to open window {:?}: {}", config.title, error),
+                    }
+                }
+
+                //nothing to draw into while every window is minimized
+                if ctx.renderer.viewports.values().all(|viewport| viewport.is_minimized()) {
+                    *control_flow = ControlFlow::Wait;
+                    return;
+                }
+
+                let now = Instant::now();
+                if let Some(deadline) = clock.wait_until(now) {
+                    *control_flow = ControlFlow::WaitUntil(deadline);
+                    return;
+                }
+                *control_flow = ControlFlow::Poll;
+
+                let (delta, steps) = clock.tick(now);
+                let dt = delta.as_secs_f32();
+                ctx.time.delta = dt;
+                ctx.time.elapsed = now - clock.start;
+
+                for _ in 0..steps {
+                    app.fixed_update(&mut ctx, clock.fixed_step.as_secs_f32());
+                    ctx.time.fixed_steps += 1;
+                }
+                ctx.time.alpha = clock.alpha();
+
+                let printf = ctx.renderer.debug.as_ref().map(|debug| debug.drain_printf()).unwrap_or_default();
+                if !printf.is_empty() {
+                    app.on_shader_printf(&mut ctx, &printf);
+                }
+
+                app.update(&mut ctx, dt);
+                ctx.input.end_frame();
+                ctx.time.frame += 1;
+
+                for viewport in ctx.renderer.viewports.values() {
+                    viewport.window.handle.request_redraw();
+                }
+            },
+            Event::RedrawRequested(window_id) => {
+                if let Some(frame) = ctx.renderer.begin_frame(window_id) {
+                    //the graph calls back into the app while recording
+                    let app = RefCell::new(&mut app);
+                    let time = &ctx.time;
+                    ctx.renderer.end_frame(frame, &|pass| app.borrow_mut().render(time, &frame, pass));
+                }
+            },
+            _ => {}
+        }
+
+        if ctx.exit && *control_flow != ControlFlow::Exit {
+            ctx.renderer.save_window_geometry();
+            *control_flow = ControlFlow::Exit;
+        }
+    })
+}
@@ -0,0 +1,130 @@
+use crate::input::Input;
+use crate::renderer::camera::Camera;
+
+use glam::{EulerRot, Quat, Vec3};
+use winit::event::MouseButton;
+
+//just short of straight up or down, where yaw stops being well defined
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+fn orientation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0)
+}
+
+fn yaw_pitch(camera: &Camera) -> (f32, f32) {
+    let (yaw, pitch, _) = camera.orientation.to_euler(EulerRot::YXZ);
+    (yaw, pitch)
+}
+
+//moves with the move_* actions, looks around with the mouse and the look_* actions
+pub struct FlyController {
+    pub yaw: f32,
+    pub pitch: f32,
+    //units per second, scrolling scales it
+    pub speed: f32,
+    pub sprint_multiplier: f32,
+    //radians per pixel
+    pub sensitivity: f32,
+    //radians per second with the look_* actions fully down, like a stick pushed all the way
+    pub look_speed: f32,
+    //looks around with the mouse only while held, always when unset
+    pub look_button: Option<MouseButton>
+}
+
+impl FlyController {
+    pub fn new(camera: &Camera) -> Self {
+        let (yaw, pitch) = yaw_pitch(camera);
+
+        Self {
+            yaw,
+            pitch,
+            speed: 5.0,
+            sprint_multiplier: 4.0,
+            sensitivity: 0.003,
+            look_speed: 2.5,
+            look_button: Some(MouseButton::Right)
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, dt: f32) {
+        if self.look_button.is_none_or(|button| input.button_down(button)) {
+            self.yaw -= input.mouse_delta[0] * self.sensitivity;
+            self.pitch = (self.pitch - input.mouse_delta[1] * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        self.yaw -= input.axis("look_left", "look_right") * self.look_speed * dt;
+        self.pitch = (self.pitch + input.axis("look_down", "look_up") * self.look_speed * dt).clamp(-MAX_PITCH, MAX_PITCH);
+        camera.orientation = orientation(self.yaw, self.pitch);
+
+        self.speed *= 1.1f32.powf(input.wheel[1]);
+
+        let movement = camera.right() * input.axis("move_left", "move_right")
+            + Vec3::Y * input.axis("move_down", "move_up")
+            + camera.forward() * input.axis("move_back", "move_forward");
+
+        let speed = if input.action_down("sprint") { self.speed * self.sprint_multiplier } else { self.speed };
+        camera.position += movement.normalize_or_zero() * speed * dt;
+    }
+}
+
+//circles a target, dragging rotates and pans, scrolling zooms
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    //radians per pixel
+    pub sensitivity: f32,
+    //fraction of the distance per wheel line
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub rotate_button: MouseButton,
+    pub pan_button: MouseButton
+}
+
+impl OrbitController {
+    //keeps the camera where it is, orbiting the target
+    pub fn new(camera: &Camera, target: Vec3) -> Self {
+        let offset = camera.position - target;
+        let distance = offset.length().max(0.01);
+
+        let yaw = offset.x.atan2(offset.z);
+        let pitch = -(offset.y / distance).clamp(-1.0, 1.0).asin();
+
+        Self {
+            target,
+            distance,
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.01,
+            max_distance: f32::MAX,
+            rotate_button: MouseButton::Left,
+            pan_button: MouseButton::Middle
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input) {
+        let [dx, dy] = input.mouse_delta;
+
+        if input.button_down(self.rotate_button) {
+            self.yaw -= dx * self.sensitivity;
+            self.pitch = (self.pitch - dy * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let rotation = orientation(self.yaw, self.pitch);
+
+        //moves the target in the view plane, scaled so it follows the cursor at the target's depth
+        if input.button_down(self.pan_button) {
+            let scale = self.distance * self.sensitivity * 0.2;
+            self.target += rotation * Vec3::new(-dx * scale, dy * scale, 0.0);
+        }
+
+        self.distance = (self.distance * (1.0 - self.zoom_speed).powf(input.wheel[1]))
+            .clamp(self.min_distance, self.max_distance);
+
+        camera.orientation = rotation;
+        camera.position = self.target + rotation * Vec3::new(0.0, 0.0, self.distance);
+    }
+}
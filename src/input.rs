@@ -0,0 +1,518 @@
+use anyhow::{Context, Result, anyhow};
+#[cfg(feature = "gamepad")]
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use serde::{Deserialize, Serialize, de::IntoDeserializer};
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}};
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent
+};
+
+const DEFAULT_BINDINGS_PATH: &str = "input.toml";
+
+//a line of wheel scrolling in pixels, for touchpads reporting pixel deltas
+const PIXELS_PER_LINE: f32 = 20.0;
+
+//how far an axis has to be pushed for actions bound to it to be down
+#[cfg(feature = "gamepad")]
+const AXIS_THRESHOLD: f32 = 0.5;
+
+#[cfg(feature = "gamepad")]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AxisDirection {
+    Negative,
+    Positive
+}
+
+//written as winit key names like "W" or "Space", as "MouseLeft", "MouseRight", "MouseMiddle" and "Mouse4",
+//as gilrs button names like "GamepadSouth" or "GamepadDPadUp", or as gilrs axis names with a direction
+//like "GamepadLeftStickX+" and "GamepadLeftStickX-", gamepad bindings apply to every connected gamepad
+//and need the gamepad feature
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    #[cfg(feature = "gamepad")]
+    Gamepad(Button),
+    #[cfg(feature = "gamepad")]
+    GamepadAxis(Axis, AxisDirection)
+}
+
+impl TryFrom<String> for Binding {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> Result<Self> {
+        #[cfg(not(feature = "gamepad"))]
+        if name.starts_with("Gamepad") {
+            anyhow::bail!("{:?} needs the gamepad feature", name);
+        }
+
+        #[cfg(feature = "gamepad")]
+        if let Some(input) = name.strip_prefix("Gamepad") {
+            let (input, direction) = match input.as_bytes().last() {
+                Some(b'+') => (&input[..input.len() - 1], Some(AxisDirection::Positive)),
+                Some(b'-') => (&input[..input.len() - 1], Some(AxisDirection::Negative)),
+                _ => (input, None),
+            };
+
+            let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> = input.into_deserializer();
+            return match direction {
+                Some(direction) => Axis::deserialize(deserializer).map(|axis| Binding::GamepadAxis(axis, direction)),
+                None => Button::deserialize(deserializer).map(Binding::Gamepad),
+            }.map_err(|_| anyhow!("unknown gamepad input {:?}", name));
+        }
+
+        if let Some(button) = name.strip_prefix("Mouse") {
+            return match button {
+                "Left" => Ok(Binding::Mouse(MouseButton::Left)),
+                "Right" => Ok(Binding::Mouse(MouseButton::Right)),
+                "Middle" => Ok(Binding::Mouse(MouseButton::Middle)),
+                _ => button.parse()
+                    .map(|index| Binding::Mouse(MouseButton::Other(index)))
+                    .map_err(|_| anyhow!("unknown mouse button {:?}", name)),
+            };
+        }
+
+        let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> = name.as_str().into_deserializer();
+        VirtualKeyCode::deserialize(deserializer)
+            .map(Binding::Key)
+            .map_err(|_| anyhow!("unknown key {:?}", name))
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        match binding {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(MouseButton::Left) => "MouseLeft".to_owned(),
+            Binding::Mouse(MouseButton::Right) => "MouseRight".to_owned(),
+            Binding::Mouse(MouseButton::Middle) => "MouseMiddle".to_owned(),
+            Binding::Mouse(MouseButton::Other(index)) => format!("Mouse{}", index),
+            #[cfg(feature = "gamepad")]
+            Binding::Gamepad(button) => format!("Gamepad{:?}", button),
+            #[cfg(feature = "gamepad")]
+            Binding::GamepadAxis(axis, AxisDirection::Positive) => format!("Gamepad{:?}+", axis),
+            #[cfg(feature = "gamepad")]
+            Binding::GamepadAxis(axis, AxisDirection::Negative) => format!("Gamepad{:?}-", axis),
+        }
+    }
+}
+
+//named actions, each bound to any number of keys and buttons
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ActionMap {
+    pub actions: HashMap<String, Vec<Binding>>
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        use Binding::Key;
+
+        let bindings = [
+            ("move_forward", vec![Key(VirtualKeyCode::W), Key(VirtualKeyCode::Up)]),
+            ("move_back", vec![Key(VirtualKeyCode::S), Key(VirtualKeyCode::Down)]),
+            ("move_left", vec![Key(VirtualKeyCode::A), Key(VirtualKeyCode::Left)]),
+            ("move_right", vec![Key(VirtualKeyCode::D), Key(VirtualKeyCode::Right)]),
+            ("move_up", vec![Key(VirtualKeyCode::E), Key(VirtualKeyCode::Space)]),
+            ("move_down", vec![Key(VirtualKeyCode::Q), Key(VirtualKeyCode::LControl)]),
+            ("sprint", vec![Key(VirtualKeyCode::LShift)]),
+            ("toggle_fullscreen", vec![Key(VirtualKeyCode::F11)]),
+            ("quit", vec![Key(VirtualKeyCode::Escape)]),
+        ];
+
+        let mut map = Self {
+            actions: bindings.into_iter()
+                .map(|(name, bindings)| (name.to_owned(), bindings))
+                .collect()
+        };
+        for (action, binding) in default_gamepad_bindings() {
+            map.bind(action, binding);
+        }
+        map
+    }
+}
+
+//after the keys, sticks and triggers move and look around
+#[cfg(feature = "gamepad")]
+fn default_gamepad_bindings() -> Vec<(&'static str, Binding)> {
+    use AxisDirection::{Negative, Positive};
+    use Binding::{Gamepad, GamepadAxis};
+
+    vec![
+        ("move_forward", GamepadAxis(Axis::LeftStickY, Positive)),
+        ("move_back", GamepadAxis(Axis::LeftStickY, Negative)),
+        ("move_left", GamepadAxis(Axis::LeftStickX, Negative)),
+        ("move_right", GamepadAxis(Axis::LeftStickX, Positive)),
+        ("move_up", Gamepad(Button::RightTrigger2)),
+        ("move_down", Gamepad(Button::LeftTrigger2)),
+        ("look_left", GamepadAxis(Axis::RightStickX, Negative)),
+        ("look_right", GamepadAxis(Axis::RightStickX, Positive)),
+        ("look_up", GamepadAxis(Axis::RightStickY, Positive)),
+        ("look_down", GamepadAxis(Axis::RightStickY, Negative)),
+        ("sprint", Gamepad(Button::LeftThumb)),
+    ]
+}
+
+#[cfg(not(feature = "gamepad"))]
+fn default_gamepad_bindings() -> Vec<(&'static str, Binding)> {
+    vec![]
+}
+
+impl ActionMap {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {:?}", path))?;
+
+        toml::from_str(&text).with_context(|| format!("failed to parse {:?}", path))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    //LVE_INPUT names the file, input.toml is used when present, the default bindings otherwise
+    pub fn from_env() -> Result<Self> {
+        match std::env::var_os("LVE_INPUT").map(PathBuf::from) {
+            Some(path) => Self::load(path),
+            None if Path::new(DEFAULT_BINDINGS_PATH).exists() => Self::load(DEFAULT_BINDINGS_PATH),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_owned()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    Down,
+    Pressed,
+    Released
+}
+
+//tracks a set of keys or buttons, pressed and released are cleared every frame
+struct Buttons<T> {
+    down: HashSet<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>
+}
+
+impl<T: Copy + Eq + std::hash::Hash> Buttons<T> {
+    fn new() -> Self {
+        Self {
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new()
+        }
+    }
+
+    fn update(&mut self, button: T, state: ElementState) {
+        match state {
+            //key repeats arrive as more presses while already down
+            ElementState::Pressed => if self.down.insert(button) {
+                self.pressed.insert(button);
+            },
+            ElementState::Released => if self.down.remove(&button) {
+                self.released.insert(button);
+            },
+        }
+    }
+
+    fn get(&self, edge: Edge) -> &HashSet<T> {
+        match edge {
+            Edge::Down => &self.down,
+            Edge::Pressed => &self.pressed,
+            Edge::Released => &self.released,
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.released.extend(self.down.drain());
+    }
+
+    fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+//gamepads have no winit events, they are polled once per frame before the app updates
+#[cfg(feature = "gamepad")]
+struct Gamepads {
+    //None when gamepads aren't supported on this system
+    gilrs: Option<Gilrs>,
+    buttons: Buttons<(GamepadId, Button)>,
+    //between -1 and 1, the previous frame's values tell when a direction was pushed past the threshold
+    axes: HashMap<(GamepadId, Axis), f32>,
+    previous_axes: HashMap<(GamepadId, Axis), f32>
+}
+
+#[cfg(feature = "gamepad")]
+impl Gamepads {
+    fn new() -> Self {
+        let gilrs = Gilrs::new()
+            .map_err(|error| log::warn!("gamepads are not available: {}", error))
+            .ok();
+
+        Self {
+            gilrs,
+            buttons: Buttons::new(),
+            axes: HashMap::new(),
+            previous_axes: HashMap::new()
+        }
+    }
+
+    //like keys, gamepads only count while a window has focus
+    fn poll(&mut self, focused: bool) {
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+
+        while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+            if !focused {
+                continue;
+            }
+
+            match event {
+                EventType::ButtonPressed(button, _) => self.buttons.update((id, button), ElementState::Pressed),
+                EventType::ButtonReleased(button, _) => self.buttons.update((id, button), ElementState::Released),
+                EventType::AxisChanged(axis, value, _) => {
+                    self.axes.insert((id, axis), value);
+                },
+                EventType::Disconnected => {
+                    let held: Vec<_> = self.buttons.down.iter().copied().filter(|&(pad, _)| pad == id).collect();
+                    for button in held {
+                        self.buttons.update(button, ElementState::Released);
+                    }
+                    self.axes.retain(|&(pad, _), _| pad != id);
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.buttons.release_all();
+        self.axes.clear();
+    }
+
+    fn end_frame(&mut self) {
+        self.buttons.end_frame();
+        self.previous_axes.clone_from(&self.axes);
+    }
+
+    fn button_in(&self, button: Button, edge: Edge) -> bool {
+        self.buttons.get(edge).iter().any(|&(_, pad_button)| pad_button == button)
+    }
+
+    fn axis_in(&self, axis: Axis, direction: AxisDirection, edge: Edge) -> bool {
+        let (now, before) = (Self::axis_down(&self.axes, axis, direction), Self::axis_down(&self.previous_axes, axis, direction));
+        match edge {
+            Edge::Down => now,
+            Edge::Pressed => now && !before,
+            Edge::Released => !now && before,
+        }
+    }
+
+    //how far the axis is pushed in the direction on the gamepad pushing it the furthest, between 0 and 1
+    fn axis_value(axes: &HashMap<(GamepadId, Axis), f32>, axis: Axis, direction: AxisDirection) -> f32 {
+        let sign = if direction == AxisDirection::Positive { 1.0 } else { -1.0 };
+        axes.iter()
+            .filter(|((_, pad_axis), _)| *pad_axis == axis)
+            .map(|(_, value)| (value * sign).clamp(0.0, 1.0))
+            .fold(0.0, f32::max)
+    }
+
+    fn axis_down(axes: &HashMap<(GamepadId, Axis), f32>, axis: Axis, direction: AxisDirection) -> bool {
+        Self::axis_value(axes, axis, direction) >= AXIS_THRESHOLD
+    }
+}
+
+pub struct Input {
+    keys: Buttons<VirtualKeyCode>,
+    buttons: Buttons<MouseButton>,
+    #[cfg(feature = "gamepad")]
+    gamepads: Gamepads,
+    //window coordinates in physical pixels
+    pub mouse_position: [f32; 2],
+    //raw device motion, unaffected by the cursor hitting the screen edge
+    pub mouse_delta: [f32; 2],
+    //in lines, positive is away from the user and to the right
+    pub wheel: [f32; 2],
+    //characters typed this frame
+    pub text: String,
+    pub modifiers: ModifiersState,
+    pub focused: bool,
+    pub cursor_inside: bool,
+    pub actions: ActionMap
+}
+
+impl Input {
+    pub fn new(actions: ActionMap) -> Self {
+        Self {
+            keys: Buttons::new(),
+            buttons: Buttons::new(),
+            #[cfg(feature = "gamepad")]
+            gamepads: Gamepads::new(),
+            mouse_position: [0.0; 2],
+            mouse_delta: [0.0; 2],
+            wheel: [0.0; 2],
+            text: String::new(),
+            modifiers: ModifiersState::empty(),
+            focused: true,
+            cursor_inside: false,
+            actions
+        }
+    }
+
+    pub fn handle_event<T>(&mut self, event: &Event<T>) {
+        match event {
+            Event::WindowEvent { event, .. } => self.handle_window_event(event),
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } if self.focused => {
+                self.mouse_delta[0] += delta.0 as f32;
+                self.mouse_delta[1] += delta.1 as f32;
+            },
+            #[cfg(feature = "gamepad")]
+            Event::MainEventsCleared => self.gamepads.poll(self.focused),
+            _ => {}
+        }
+    }
+
+    fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { virtual_keycode: Some(key), state, .. },
+                ..
+            } => self.keys.update(*key, *state),
+            WindowEvent::MouseInput { button, state, .. } => self.buttons.update(*button, *state),
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse_position = [position.x as f32, position.y as f32];
+            },
+            WindowEvent::CursorEntered { .. } => self.cursor_inside = true,
+            WindowEvent::CursorLeft { .. } => self.cursor_inside = false,
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (*x, *y),
+                    MouseScrollDelta::PixelDelta(position) => (position.x as f32 / PIXELS_PER_LINE, position.y as f32 / PIXELS_PER_LINE),
+                };
+                self.wheel[0] += x;
+                self.wheel[1] += y;
+            },
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => self.text.push(*c),
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            //releases never arrive for keys held while focus moves elsewhere
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                if !focused {
+                    self.keys.release_all();
+                    self.buttons.release_all();
+                    #[cfg(feature = "gamepad")]
+                    self.gamepads.release_all();
+                    self.modifiers = ModifiersState::empty();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    //call once all events of a frame were handled and the frame used them
+    pub fn end_frame(&mut self) {
+        self.keys.end_frame();
+        self.buttons.end_frame();
+        #[cfg(feature = "gamepad")]
+        self.gamepads.end_frame();
+        self.mouse_delta = [0.0; 2];
+        self.wheel = [0.0; 2];
+        self.text.clear();
+    }
+
+    pub fn key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys.down.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys.pressed.contains(&key)
+    }
+
+    pub fn key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys.released.contains(&key)
+    }
+
+    pub fn button_down(&self, button: MouseButton) -> bool {
+        self.buttons.down.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons.pressed.contains(&button)
+    }
+
+    pub fn button_released(&self, button: MouseButton) -> bool {
+        self.buttons.released.contains(&button)
+    }
+
+    //on any gamepad, 0 when no gamepad has the axis
+    #[cfg(feature = "gamepad")]
+    pub fn gamepad_axis(&self, axis: Axis) -> f32 {
+        Gamepads::axis_value(&self.gamepads.axes, axis, AxisDirection::Positive)
+            - Gamepads::axis_value(&self.gamepads.axes, axis, AxisDirection::Negative)
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn gamepad_button_down(&self, button: Button) -> bool {
+        self.gamepads.button_in(button, Edge::Down)
+    }
+
+    fn action_in(&self, action: &str, edge: Edge) -> bool {
+        self.actions.bindings(action).iter().any(|binding| match *binding {
+            Binding::Key(key) => self.keys.get(edge).contains(&key),
+            Binding::Mouse(button) => self.buttons.get(edge).contains(&button),
+            #[cfg(feature = "gamepad")]
+            Binding::Gamepad(button) => self.gamepads.button_in(button, edge),
+            #[cfg(feature = "gamepad")]
+            Binding::GamepadAxis(axis, direction) => self.gamepads.axis_in(axis, direction, edge),
+        })
+    }
+
+    pub fn action_down(&self, action: &str) -> bool {
+        self.action_in(action, Edge::Down)
+    }
+
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.action_in(action, Edge::Pressed)
+    }
+
+    pub fn action_released(&self, action: &str) -> bool {
+        self.action_in(action, Edge::Released)
+    }
+
+    //between 0 and 1, keys and buttons are all or nothing while axes bound to the action count as far as they are pushed
+    pub fn action_value(&self, action: &str) -> f32 {
+        self.actions.bindings(action).iter().map(|binding| match *binding {
+            Binding::Key(key) => self.key_down(key) as i32 as f32,
+            Binding::Mouse(button) => self.button_down(button) as i32 as f32,
+            #[cfg(feature = "gamepad")]
+            Binding::Gamepad(button) => self.gamepad_button_down(button) as i32 as f32,
+            #[cfg(feature = "gamepad")]
+            Binding::GamepadAxis(axis, direction) => Gamepads::axis_value(&self.gamepads.axes, axis, direction),
+        }).fold(0.0, f32::max)
+    }
+
+    //between -1 and 1 from a pair of opposing actions
+    pub fn axis(&self, negative: &str, positive: &str) -> f32 {
+        self.action_value(positive) - self.action_value(negative)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod renderer;
+pub mod input;
+pub mod app;
+pub mod controller;
@@ -1,90 +1,87 @@
-mod renderer;
-use ash::vk;
-use winit::event::{Event, WindowEvent};
+use lve::{app::{self, App, Context, Time}, renderer};
+use lve::controller::FlyController;
+use renderer::{Frame, camera::Camera, graph::PassContext, scene::Scene, scene_renderer::SceneRenderer};
+use winit::window::WindowId;
 
-fn main() {
-    let mut renderer = renderer::Renderer::new();
-
-    let event_loop = renderer.window.event_loop().unwrap();
+struct Demo {
+    camera: Camera,
+    controller: FlyController,
+    //loaded from LVE_SCENE, drawn into the primary window
+    scene: Option<SceneRenderer>,
+    primary: Option<WindowId>
+}
 
-    let graphics_queue = renderer.device.graphics_family.queues[0];
+impl Demo {
+    fn new() -> Self {
+        let mut camera = Camera::new_perspective(60f32.to_radians(), 0.1, None)
+            .with_position(glam::Vec3::new(0.0, 1.0, 5.0));
+        camera.look_at(glam::Vec3::ZERO, glam::Vec3::Y);
 
-    event_loop.run(move |event, _, control_flow| {
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => {
-                *control_flow = winit::event_loop::ControlFlow::Exit;
-            },
-            Event::RedrawRequested(_) => {
-                // acquiring next image:
-                renderer.swapchain.current_image = (renderer.swapchain.current_image + 1) % renderer.swapchain.image_count as usize;
+        Self {
+            controller: FlyController::new(&camera),
+            camera,
+            scene: None,
+            primary: None
+        }
+    }
 
-                let (image_index, renew_pipeline) = unsafe {
-                    renderer.swapchain.loader.acquire_next_image(
-                        renderer.swapchain.swapchain,
-                        u64::MAX,
-                        renderer.swapchain.image_available_semaphores[renderer.swapchain.current_image],
-                        vk::Fence::null(),
-                    ).unwrap()
-                };
-                let image_index = image_index as usize;
+    //starts at the scene's first camera when it has one
+    fn load_scene(&mut self, ctx: &Context, path: &str) -> anyhow::Result<()> {
+        let scene = Scene::load(&ctx.renderer.device, path)?;
 
-                // fences:
-                unsafe {
-                    let fences = [renderer.swapchain.start_draw_fences[renderer.swapchain.current_image]];
+        if let Some((camera, world)) = scene.camera_nodes().next() {
+            self.camera = Camera::from_scene(camera, world, self.camera.aspect);
+            self.controller = FlyController::new(&self.camera);
+        }
 
-                    renderer.device.logical.wait_for_fences(
-                        &fences,
-                        true,
-                        u64::MAX,
-                    ).unwrap();
+        self.scene = Some(SceneRenderer::new(&ctx.renderer, scene));
+        Ok(())
+    }
+}
 
-                    renderer.device.logical.reset_fences(
-                        &fences,
-                    ).unwrap();
-                };
+impl App for Demo {
+    fn init(&mut self, ctx: &mut Context) {
+        self.primary = Some(ctx.renderer.primary);
 
-                // submit:
-                let semaphores_available = [renderer.swapchain.image_available_semaphores[renderer.swapchain.current_image]];
-                let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-                let semaphores_finished = [renderer.swapchain.render_finished_semaphores[renderer.swapchain.current_image]];
-                let command_buffers = [renderer.command_buffers[image_index]];
+        if let Ok(path) = std::env::var("LVE_SCENE") {
+            if let Err(error) = self.load_scene(ctx, &path) {
+                log::error!("failed to load scene {:?}: {}", path, error);
+            }
+        }
 
-                let submit_info = [
-                    vk::SubmitInfo::builder()
-                        .wait_semaphores(&semaphores_available)
-                        .wait_dst_stage_mask(&waiting_stages)
-                        .command_buffers(&command_buffers)
-                        .signal_semaphores(&semaphores_finished)
-                        .build()
-                ];
+        self.camera.set_extent(ctx.renderer.primary_viewport().swapchain.extent);
+    }
 
-                unsafe {
-                    renderer.device.logical.queue_submit(
-                        graphics_queue,
-                        &submit_info,
-                        renderer.swapchain.start_draw_fences[renderer.swapchain.current_image],
-                    ).unwrap();
-                };
+    fn update(&mut self, ctx: &mut Context, dt: f32) {
+        if ctx.input.action_pressed("quit") {
+            ctx.exit();
+        }
+        if ctx.input.action_pressed("toggle_fullscreen") {
+            let primary = ctx.renderer.primary;
+            ctx.renderer.toggle_fullscreen(primary);
+        }
 
-                // present:
-                let swapchains = [renderer.swapchain.swapchain];
-                let indices = [image_index as u32];
+        self.controller.update(&mut self.camera, &ctx.input, dt);
+    }
 
-                let present_info = vk::PresentInfoKHR::builder()
-                    .wait_semaphores(&semaphores_finished)
-                    .swapchains(&swapchains)
-                    .image_indices(&indices);
+    fn render(&mut self, _time: &Time, frame: &Frame, pass: &PassContext) {
+        if let Some(scene) = &mut self.scene {
+            if Some(frame.window) == self.primary {
+                scene.draw(frame, pass, &self.camera);
+            }
+        }
+    }
 
-                unsafe {
-                    renderer.swapchain.loader
-                        .queue_present(graphics_queue, &present_info)
-                        .unwrap();
-                };
-            },
-            _ => {}
+    fn on_resize(&mut self, ctx: &mut Context, window: WindowId, width: u32, height: u32) {
+        if window == ctx.renderer.primary {
+            self.camera.set_aspect(width, height);
         }
-    });
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn,vulkan::printf=info")).init();
+
+    let config = renderer::config::RendererConfig::from_env_and_args().unwrap();
+    app::run(config, Demo::new()).unwrap();
 }
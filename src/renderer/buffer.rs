@@ -0,0 +1,101 @@
+use super::Device;
+
+use ash::vk;
+use gpu_allocator::{MemoryLocation, vulkan::{Allocation, AllocationCreateDesc}};
+use std::sync::Arc;
+
+pub struct Buffer {
+    pub device: Arc<Device>,
+    pub buffer: vk::Buffer,
+    pub allocation: Allocation,
+    pub size: vk::DeviceSize
+}
+
+impl Buffer {
+    pub fn new(device: &Arc<Device>, size: vk::DeviceSize, usage: vk::BufferUsageFlags, location: MemoryLocation) -> Self {
+        Self::new_with_families(device, size, usage, location, &[])
+    }
+
+    //buffers used by both graphics and async compute are shared concurrently
+    pub fn new_shared(device: &Arc<Device>, size: vk::DeviceSize, usage: vk::BufferUsageFlags, location: MemoryLocation) -> Self {
+        let families = device.queue_family_indices();
+        Self::new_with_families(device, size, usage, location, &families)
+    }
+
+    fn new_with_families(
+        device: &Arc<Device>,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+        families: &[u32])
+    -> Self {
+        let sharing_mode = if families.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
+
+        let info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(sharing_mode)
+            .queue_family_indices(if families.len() > 1 { families } else { &[] });
+
+        let buffer = unsafe {
+            device.logical.create_buffer(&info, None).unwrap()
+        };
+
+        let requirements = unsafe {
+            device.logical.get_buffer_memory_requirements(buffer)
+        };
+
+        let allocation = device.allocator.lock().unwrap().allocate(&AllocationCreateDesc {
+            name: "buffer",
+            requirements,
+            location,
+            linear: true
+        }).unwrap();
+
+        unsafe {
+            device.logical.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()).unwrap();
+        }
+
+        Self {
+            device: device.clone(),
+            buffer,
+            allocation,
+            size
+        }
+    }
+
+    pub fn new_staging(device: &Arc<Device>, data: &[u8]) -> Self {
+        let mut staging = Self::new(
+            device,
+            data.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu);
+
+        staging.write(0, data);
+        staging.set_name("staging buffer");
+        staging
+    }
+
+    pub fn set_name(&self, name: &str) {
+        self.device.set_object_name(self.buffer, name);
+    }
+
+    pub fn write(&mut self, offset: usize, data: &[u8]) {
+        let mapped = self.allocation.mapped_slice_mut().expect("buffer is not host visible");
+        mapped[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.device.allocator.lock().unwrap().free(std::mem::take(&mut self.allocation)).unwrap();
+        unsafe {
+            self.device.logical.destroy_buffer(self.buffer, None);
+        }
+    }
+}
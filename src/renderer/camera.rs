@@ -0,0 +1,218 @@
+use super::scene::{Projection, SceneCamera};
+
+use ash::vk;
+use glam::camera::rh::proj::vulkan;
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+//right handed, y up, looking down -z
+#[derive(Clone, Copy, Debug)]
+pub enum CameraProjection {
+    //an unset far plane is infinitely far away
+    Perspective { fov_y: f32, near: f32, far: Option<f32> },
+    //height of the view volume in world units, the width follows the aspect ratio
+    Orthographic { height: f32, near: f32, far: f32 }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub projection: CameraProjection,
+    //width over height
+    pub aspect: f32
+}
+
+//std140 compatible, for uniform buffers
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniform {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub view_projection: [[f32; 4]; 4],
+    pub position: [f32; 4]
+}
+
+impl Camera {
+    pub fn new_perspective(fov_y: f32, near: f32, far: Option<f32>) -> Self {
+        Self::new(CameraProjection::Perspective { fov_y, near, far })
+    }
+
+    pub fn new_orthographic(height: f32, near: f32, far: f32) -> Self {
+        Self::new(CameraProjection::Orthographic { height, near, far })
+    }
+
+    fn new(projection: CameraProjection) -> Self {
+        Self {
+            position: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+            projection,
+            aspect: 1.0
+        }
+    }
+
+    //a camera placed at a glTF camera node, scale is dropped,
+    //the aspect is kept for perspective cameras that don't specify one
+    pub fn from_scene(camera: &SceneCamera, world: Mat4, aspect: f32) -> Self {
+        let (_, orientation, position) = world.to_scale_rotation_translation();
+
+        let (projection, aspect) = match camera.projection {
+            Projection::Perspective { yfov, aspect_ratio, znear, zfar } =>
+                (CameraProjection::Perspective { fov_y: yfov, near: znear, far: zfar }, aspect_ratio.unwrap_or(aspect)),
+            Projection::Orthographic { xmag, ymag, znear, zfar } =>
+                (CameraProjection::Orthographic { height: 2.0 * ymag, near: znear, far: zfar }, xmag / ymag),
+        };
+
+        Self {
+            position,
+            orientation,
+            projection,
+            aspect
+        }
+    }
+
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let forward = (target - self.position).normalize_or_zero();
+        if forward == Vec3::ZERO {
+            return;
+        }
+
+        let right = forward.cross(up).normalize_or_zero();
+        if right == Vec3::ZERO {
+            return;
+        }
+
+        //the camera's local axes in world space, it looks down -z
+        self.orientation = Quat::from_mat3(&Mat3::from_cols(right, right.cross(forward), -forward));
+    }
+
+    pub fn set_aspect(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    pub fn set_extent(&mut self, extent: vk::Extent2D) {
+        self.set_aspect(extent.width, extent.height);
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.orientation * Vec3::NEG_Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.orientation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.orientation * Vec3::Y
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.orientation, self.position).inverse()
+    }
+
+    //vulkan clip space with reversed depth: y points down, near maps to 1 and far to 0,
+    //so depth tests use GREATER and depth buffers clear to 0
+    pub fn projection_matrix(&self) -> Mat4 {
+        match self.projection {
+            CameraProjection::Perspective { fov_y, near, far: None } =>
+                vulkan::perspective_infinite_reverse(fov_y, self.aspect, near),
+            CameraProjection::Perspective { fov_y, near, far: Some(far) } =>
+                vulkan::perspective(fov_y, self.aspect, far, near),
+            CameraProjection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect;
+                vulkan::orthographic(-half_width, half_width, -half_height, half_height, far, near)
+            },
+        }
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection_matrix() * self.view()
+    }
+
+    pub fn uniform(&self) -> CameraUniform {
+        let view = self.view();
+        let projection = self.projection_matrix();
+
+        CameraUniform {
+            view: view.to_cols_array_2d(),
+            projection: projection.to_cols_array_2d(),
+            view_projection: (projection * view).to_cols_array_2d(),
+            position: self.position.extend(1.0).to_array()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4;
+
+    //depth and y of a view space point in normalized device coordinates
+    fn project(camera: &Camera, point: Vec3) -> (f32, f32) {
+        let clip = camera.projection_matrix() * point.extend(1.0);
+        (clip.z / clip.w, clip.y / clip.w)
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn perspective_maps_near_to_1_and_far_to_0() {
+        let camera = Camera::new_perspective(1.0, 0.1, Some(100.0));
+
+        assert_near(project(&camera, Vec3::new(0.0, 0.0, -0.1)).0, 1.0);
+        assert_near(project(&camera, Vec3::new(0.0, 0.0, -100.0)).0, 0.0);
+    }
+
+    #[test]
+    fn infinite_perspective_approaches_0_far_away() {
+        let camera = Camera::new_perspective(1.0, 0.1, None);
+
+        assert_near(project(&camera, Vec3::new(0.0, 0.0, -0.1)).0, 1.0);
+        let (depth, _) = project(&camera, Vec3::new(0.0, 0.0, -1.0e6));
+        assert!(depth > 0.0 && depth < 1e-6, "{}", depth);
+    }
+
+    #[test]
+    fn orthographic_maps_near_to_1_and_far_to_0() {
+        let camera = Camera::new_orthographic(2.0, 0.5, 50.0);
+
+        assert_near(project(&camera, Vec3::new(0.0, 0.0, -0.5)).0, 1.0);
+        assert_near(project(&camera, Vec3::new(0.0, 0.0, -50.0)).0, 0.0);
+        assert_near(project(&camera, Vec3::new(0.0, 1.0, -10.0)).1, -1.0);
+    }
+
+    #[test]
+    fn y_points_down_in_clip_space() {
+        let camera = Camera::new_perspective(1.0, 0.1, Some(100.0));
+
+        let (_, y) = project(&camera, Vec3::new(0.0, 1.0, -10.0));
+        assert!(y < 0.0, "{}", y);
+    }
+
+    #[test]
+    fn gltf_cameras_keep_the_aspect_they_leave_out() {
+        let perspective = |aspect_ratio| SceneCamera {
+            name: "camera".to_owned(),
+            projection: Projection::Perspective { yfov: 1.0, aspect_ratio, znear: 0.1, zfar: None }
+        };
+
+        assert_eq!(Camera::from_scene(&perspective(None), Mat4::IDENTITY, 1.5).aspect, 1.5);
+        assert_eq!(Camera::from_scene(&perspective(Some(2.0)), Mat4::IDENTITY, 1.5).aspect, 2.0);
+    }
+
+    #[test]
+    fn view_follows_the_camera_position() {
+        let camera = Camera::new_perspective(1.0, 0.1, None).with_position(Vec3::new(1.0, 2.0, 3.0));
+
+        assert_eq!(camera.view() * Vec4::new(1.0, 2.0, 3.0, 1.0), Vec4::W);
+    }
+}
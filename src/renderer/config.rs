@@ -0,0 +1,551 @@
+use super::debug::ErrorAction;
+use super::instance::ValidationFeatures;
+use super::stats::StatsDisplay;
+
+use ash::vk;
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CONFIG_PATH: &str = "lve.toml";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate
+}
+
+impl PresentMode {
+    pub fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "fifo" => Ok(PresentMode::Fifo),
+            "fifo_relaxed" => Ok(PresentMode::FifoRelaxed),
+            "mailbox" => Ok(PresentMode::Mailbox),
+            "immediate" => Ok(PresentMode::Immediate),
+            _ => bail!("unknown present mode {:?}", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FullscreenMode {
+    Windowed,
+    Borderless,
+    Exclusive
+}
+
+impl FullscreenMode {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "windowed" => Ok(FullscreenMode::Windowed),
+            "borderless" => Ok(FullscreenMode::Borderless),
+            "exclusive" => Ok(FullscreenMode::Exclusive),
+            _ => bail!("unknown fullscreen mode {:?}", value),
+        }
+    }
+}
+
+//a refresh rate of 0 picks the highest one available at that size
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct VideoModeConfig {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub refresh_rate: u16
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub title: String,
+    //logical size while windowed
+    pub width: u32,
+    pub height: u32,
+    //physical position of the outer window, placed by the platform when unset
+    pub position: Option<[i32; 2]>,
+    pub resizable: bool,
+    pub maximized: bool,
+    pub fullscreen: FullscreenMode,
+    //index into the available monitors, the primary one when unset
+    pub monitor: Option<usize>,
+    //exclusive fullscreen only, the monitor's largest mode when unset
+    pub video_mode: Option<VideoModeConfig>,
+    //the fullscreen mode toggled into at runtime
+    pub fullscreen_toggle: FullscreenMode,
+    pub remember_geometry: bool
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "lve".to_owned(),
+            width: 1024,
+            height: 768,
+            position: None,
+            resizable: true,
+            maximized: false,
+            fullscreen: FullscreenMode::Windowed,
+            monitor: None,
+            video_mode: None,
+            fullscreen_toggle: FullscreenMode::Borderless,
+            remember_geometry: true
+        }
+    }
+}
+
+//where the window was when the last run closed
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct WindowGeometry {
+    pub position: Option<[i32; 2]>,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+    pub fullscreen: FullscreenMode,
+    pub monitor: Option<usize>
+}
+
+impl WindowGeometry {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("lve").join("window.toml"))
+    }
+
+    pub fn load() -> Option<Self> {
+        let text = std::fs::read_to_string(Self::path()?).ok()?;
+        toml::from_str(&text).ok()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow!("no user config directory"))?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn apply(&self, config: &mut WindowConfig) {
+        config.position = self.position;
+        config.width = self.width;
+        config.height = self.height;
+        config.maximized = self.maximized;
+        config.fullscreen = self.fullscreen;
+        config.monitor = self.monitor;
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LoopConfig {
+    //fixed updates per second
+    pub fixed_rate: f64,
+    //caps the catch up after a stall, the rest of the backlog is dropped
+    pub max_fixed_steps: u32,
+    //frames per second, unlimited when unset
+    pub frame_limit: Option<f64>
+}
+
+impl LoopConfig {
+    //both rates become intervals, so zero, negative or infinite ones are rejected
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !(self.fixed_rate.is_finite() && self.fixed_rate > 0.0) {
+            bail!("the fixed rate has to be positive, got {}", self.fixed_rate);
+        }
+        if let Some(fps) = self.frame_limit.filter(|fps| !(fps.is_finite() && *fps > 0.0)) {
+            bail!("the frame limit has to be positive, got {}", fps);
+        }
+        Ok(())
+    }
+}
+
+impl Default for LoopConfig {
+    fn default() -> Self {
+        Self {
+            fixed_rate: 60.0,
+            max_fixed_steps: 8,
+            frame_limit: None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProfilerConfig {
+    //timed scopes per frame, the passes past them are not timed
+    pub max_scopes: usize,
+    //per outermost scope, needs the pipelineStatisticsQuery feature and falls back to timestamps without it
+    pub pipeline_statistics: bool
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self {
+            max_scopes: 32,
+            pipeline_statistics: false
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    //frame timings shown in the window titles or logged once a second, or kept only for the app
+    pub display: StatsDisplay,
+    //frames the rolling statistics cover
+    pub window: usize
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            display: StatsDisplay::None,
+            window: 120
+        }
+    }
+}
+
+//defaults, then the config file, then the remembered window geometry, then LVE_* environment variables, then command line flags
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RendererConfig {
+    pub app_name: String,
+    pub engine_name: String,
+    //major and minor, at least 1.3
+    pub api_version: [u32; 2],
+    pub validation: bool,
+    pub validation_features: ValidationFeatures,
+    pub error_action: ErrorAction,
+    //requested on top of the validation layer, skipped when not installed
+    pub layers: Vec<String>,
+    pub present_mode: PresentMode,
+    pub window: WindowConfig,
+    #[serde(rename = "loop")]
+    pub game_loop: LoopConfig,
+    pub profiler: ProfilerConfig,
+    pub stats: StatsConfig
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            app_name: "Ash App".to_owned(),
+            engine_name: "Ash Engine".to_owned(),
+            api_version: [1, 3],
+            validation: cfg!(all(feature = "validation", debug_assertions)),
+            validation_features: ValidationFeatures::default(),
+            error_action: ErrorAction::Log,
+            layers: vec![],
+            present_mode: PresentMode::Fifo,
+            window: WindowConfig::default(),
+            game_loop: LoopConfig::default(),
+            profiler: ProfilerConfig::default(),
+            stats: StatsConfig::default()
+        }
+    }
+}
+
+impl RendererConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {:?}", path))?;
+
+        let config: Self = toml::from_str(&text).with_context(|| format!("failed to parse {:?}", path))?;
+        config.validate().with_context(|| format!("invalid config in {:?}", path))?;
+
+        Ok(config)
+    }
+
+    //run on every way a config is made, the renderer checks it again before using it
+    pub fn validate(&self) -> Result<()> {
+        if self.api_version < [1, 3] {
+            bail!("vulkan {}.{} was asked for, the renderer needs 1.3 or later", self.api_version[0], self.api_version[1]);
+        }
+        self.game_loop.validate()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    //--config or LVE_CONFIG name the file, lve.toml is used when present
+    pub fn from_env_and_args() -> Result<Self> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+
+        let path = args.iter()
+            .position(|arg| arg == "--config")
+            .map(|i| args.get(i + 1).map(PathBuf::from).ok_or_else(|| anyhow!("--config needs a path")))
+            .transpose()?
+            .or_else(|| std::env::var_os("LVE_CONFIG").map(PathBuf::from));
+
+        let mut config = match path {
+            Some(path) => Self::load(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::load(DEFAULT_CONFIG_PATH)?,
+            None => Self::default(),
+        };
+
+        if config.window.remember_geometry {
+            if let Some(geometry) = WindowGeometry::load() {
+                geometry.apply(&mut config.window);
+            }
+        }
+
+        config.with_env()?.with_args(args)
+    }
+
+    pub fn with_env(mut self) -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok();
+
+        if let Some(value) = var("LVE_APP_NAME") {
+            self.app_name = value;
+        }
+        if let Some(value) = var("LVE_ENGINE_NAME") {
+            self.engine_name = value;
+        }
+        if let Some(value) = var("LVE_API_VERSION") {
+            self.api_version = parse_api_version(&value)?;
+        }
+        if let Some(value) = var("LVE_VALIDATION") {
+            self.validation = parse_bool(&value)?;
+        }
+        if let Some(value) = var("LVE_VALIDATION_FEATURES") {
+            self.validation_features = ValidationFeatures::parse(&value)?;
+        }
+        if let Some(value) = var("LVE_VALIDATION_ERRORS") {
+            self.error_action = ErrorAction::parse(&value)?;
+        }
+        if let Some(value) = var("LVE_PRESENT_MODE") {
+            self.present_mode = PresentMode::parse(&value)?;
+        }
+        if let Some(value) = var("LVE_TITLE") {
+            self.window.title = value;
+        }
+        if let Some(value) = var("LVE_WIDTH") {
+            self.window.width = value.parse()?;
+        }
+        if let Some(value) = var("LVE_HEIGHT") {
+            self.window.height = value.parse()?;
+        }
+        if let Some(value) = var("LVE_FULLSCREEN") {
+            self.window.fullscreen = FullscreenMode::parse(&value)?;
+        }
+        if let Some(value) = var("LVE_MONITOR") {
+            self.window.monitor = Some(value.parse()?);
+        }
+        if let Some(value) = var("LVE_FRAME_LIMIT") {
+            self.game_loop.frame_limit = Some(value.parse()?);
+        }
+        if let Some(value) = var("LVE_PROFILER_SCOPES") {
+            self.profiler.max_scopes = value.parse()?;
+        }
+        if let Some(value) = var("LVE_PIPELINE_STATISTICS") {
+            self.profiler.pipeline_statistics = parse_bool(&value)?;
+        }
+        if let Some(value) = var("LVE_STATS") {
+            self.stats.display = StatsDisplay::parse(&value)?;
+        }
+        if let Some(value) = var("LVE_STATS_WINDOW") {
+            self.stats.window = value.parse()?;
+        }
+        self.validate()?;
+
+        Ok(self)
+    }
+
+    pub fn with_args(mut self, args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+
+            match arg.as_str() {
+                "--config" => {
+                    value()?;
+                },
+                "--app-name" => self.app_name = value()?,
+                "--engine-name" => self.engine_name = value()?,
+                "--api-version" => self.api_version = parse_api_version(&value()?)?,
+                "--validation" => self.validation = true,
+                "--no-validation" => self.validation = false,
+                "--validation-features" => self.validation_features = ValidationFeatures::parse(&value()?)?,
+                "--validation-errors" => self.error_action = ErrorAction::parse(&value()?)?,
+                "--layer" => self.layers.push(value()?),
+                "--present-mode" => self.present_mode = PresentMode::parse(&value()?)?,
+                "--title" => self.window.title = value()?,
+                "--width" => self.window.width = value()?.parse()?,
+                "--height" => self.window.height = value()?.parse()?,
+                "--resizable" => self.window.resizable = true,
+                "--no-resizable" => self.window.resizable = false,
+                "--fullscreen" => self.window.fullscreen = FullscreenMode::parse(&value()?)?,
+                "--windowed" => self.window.fullscreen = FullscreenMode::Windowed,
+                "--monitor" => self.window.monitor = Some(value()?.parse()?),
+                "--fixed-rate" => self.game_loop.fixed_rate = value()?.parse()?,
+                "--frame-limit" => self.game_loop.frame_limit = Some(value()?.parse()?),
+                "--profiler-scopes" => self.profiler.max_scopes = value()?.parse()?,
+                "--pipeline-statistics" => self.profiler.pipeline_statistics = true,
+                "--no-pipeline-statistics" => self.profiler.pipeline_statistics = false,
+                "--stats" => self.stats.display = StatsDisplay::parse(&value()?)?,
+                "--stats-window" => self.stats.window = value()?.parse()?,
+                _ => bail!("unknown argument {:?}", arg),
+            }
+        }
+        self.validate()?;
+
+        Ok(self)
+    }
+
+    pub fn with_app_name(mut self, name: &str) -> Self {
+        self.app_name = name.to_owned();
+        self
+    }
+
+    pub fn with_engine_name(mut self, name: &str) -> Self {
+        self.engine_name = name.to_owned();
+        self
+    }
+
+    pub fn with_api_version(mut self, major: u32, minor: u32) -> Self {
+        self.api_version = [major, minor];
+        self
+    }
+
+    pub fn with_validation(mut self, validation: bool) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    pub fn with_validation_features(mut self, features: ValidationFeatures) -> Self {
+        self.validation_features = features;
+        self
+    }
+
+    pub fn with_error_action(mut self, action: ErrorAction) -> Self {
+        self.error_action = action;
+        self
+    }
+
+    pub fn with_layer(mut self, name: &str) -> Self {
+        self.layers.push(name.to_owned());
+        self
+    }
+
+    pub fn with_present_mode(mut self, mode: PresentMode) -> Self {
+        self.present_mode = mode;
+        self
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.window.title = title.to_owned();
+        self
+    }
+
+    pub fn with_window_size(mut self, width: u32, height: u32) -> Self {
+        self.window.width = width;
+        self.window.height = height;
+        self
+    }
+
+    pub fn with_resizable(mut self, resizable: bool) -> Self {
+        self.window.resizable = resizable;
+        self
+    }
+
+    pub fn with_fullscreen(mut self, mode: FullscreenMode, monitor: Option<usize>) -> Self {
+        self.window.fullscreen = mode;
+        self.window.monitor = monitor;
+        self
+    }
+
+    pub fn with_video_mode(mut self, width: u32, height: u32, refresh_rate: u16) -> Self {
+        self.window.video_mode = Some(VideoModeConfig { width, height, refresh_rate });
+        self
+    }
+
+    pub fn with_fixed_rate(mut self, rate: f64) -> Self {
+        self.game_loop.fixed_rate = rate;
+        self
+    }
+
+    pub fn with_frame_limit(mut self, fps: Option<f64>) -> Self {
+        self.game_loop.frame_limit = fps;
+        self
+    }
+
+    pub fn with_profiler(mut self, max_scopes: usize, pipeline_statistics: bool) -> Self {
+        self.profiler = ProfilerConfig { max_scopes, pipeline_statistics };
+        self
+    }
+
+    pub fn with_stats(mut self, display: StatsDisplay, window: usize) -> Self {
+        self.stats = StatsConfig { display, window };
+        self
+    }
+
+    pub fn vk_api_version(&self) -> u32 {
+        vk::make_api_version(0, self.api_version[0], self.api_version[1], 0)
+    }
+}
+
+//written as major.minor, like 1.3
+fn parse_api_version(value: &str) -> Result<[u32; 2]> {
+    let (major, minor) = value.split_once('.').ok_or_else(|| anyhow!("expected a version like 1.3, got {:?}", value))?;
+    Ok([major.parse()?, minor.parse()?])
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "1" | "true" | "on" => Ok(true),
+        "0" | "false" | "off" => Ok(false),
+        _ => bail!("expected a boolean, got {:?}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn vulkan_older_than_1_3_is_rejected() {
+        assert!(RendererConfig::default().validate().is_ok());
+        assert!(RendererConfig::default().with_api_version(1, 2).validate().is_err());
+        assert!(RendererConfig::default().with_api_version(2, 0).validate().is_ok());
+
+        assert!(RendererConfig::default().with_args(args(&["--api-version", "1.2"])).is_err());
+        let config = RendererConfig::default().with_args(args(&["--api-version", "1.4", "--engine-name", "test"])).unwrap();
+        assert_eq!(config.api_version, [1, 4]);
+        assert_eq!(config.engine_name, "test");
+    }
+
+    #[test]
+    fn loop_rates_have_to_be_positive() {
+        assert!(RendererConfig::default().with_fixed_rate(0.0).validate().is_err());
+        assert!(RendererConfig::default().with_fixed_rate(f64::NAN).validate().is_err());
+        assert!(RendererConfig::default().with_frame_limit(Some(-30.0)).validate().is_err());
+        assert!(RendererConfig::default().with_frame_limit(Some(144.0)).validate().is_ok());
+    }
+
+    #[test]
+    fn api_versions_are_major_dot_minor() {
+        assert_eq!(parse_api_version("1.3").unwrap(), [1, 3]);
+        assert!(parse_api_version("1").is_err());
+        assert!(parse_api_version("1.x").is_err());
+    }
+}
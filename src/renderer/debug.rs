@@ -1,38 +1,440 @@
+use super::instance::Instance;
+
 use ash::{vk, extensions::ext};
-use std::ffi;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, ffi, fmt::Write, sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, Ordering}}};
 
-pub struct Debug {
-    loader: ext::DebugUtils,
-    messenger: vk::DebugUtilsMessengerEXT
+//undelivered printf output beyond this is dropped, oldest first
+const MAX_PRINTF_MESSAGES: usize = 1024;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MessageId {
+    Name(String),
+    Number(i32)
 }
 
-impl Debug {
-    pub fn new(entry: &ash::Entry, instance: &ash::Instance) -> Self {
-        let loader = ext::DebugUtils::new(entry, instance);
+impl MessageId {
+    fn matches(&self, name: Option<&str>, number: i32) -> bool {
+        match self {
+            MessageId::Name(id) => name == Some(id.as_str()),
+            MessageId::Number(id) => *id == number,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MessageFilter {
+    pub severities: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub types: vk::DebugUtilsMessageTypeFlagsEXT,
+    //when not empty only these messages get through
+    pub allowed: Vec<MessageId>,
+    //known false positives
+    pub denied: Vec<MessageId>
+}
+
+impl Default for MessageFilter {
+    fn default() -> Self {
+        Self {
+            severities: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            types: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+            allowed: vec![],
+            denied: vec![]
+        }
+    }
+}
+
+impl MessageFilter {
+    fn is_listed(&self, name: Option<&str>, number: i32) -> bool {
+        (self.allowed.is_empty() || self.allowed.iter().any(|id| id.matches(name, number)))
+            && !self.denied.iter().any(|id| id.matches(name, number))
+    }
+
+    fn shows(&self, severity: vk::DebugUtilsMessageSeverityFlagsEXT, ty: vk::DebugUtilsMessageTypeFlagsEXT) -> bool {
+        self.severities.intersects(severity) && self.types.intersects(ty)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorAction {
+    Log,
+    //the callback can't unwind into the driver, so the error is remembered and the frame that caused it panics
+    Panic,
+    //remembers the first error, assert_clean or dropping Debug panics with it
+    Fail
+}
+
+impl ErrorAction {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "log" => Ok(ErrorAction::Log),
+            "panic" => Ok(ErrorAction::Panic),
+            "fail" => Ok(ErrorAction::Fail),
+            _ => anyhow::bail!("unknown validation error action {:?}", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SeverityCounts {
+    pub errors: u64,
+    pub warnings: u64,
+    pub infos: u64,
+    pub verbose: u64
+}
+
+impl SeverityCounts {
+    fn new(counts: &[AtomicU64; 4]) -> Self {
+        Self {
+            errors: counts[0].load(Ordering::Relaxed),
+            warnings: counts[1].load(Ordering::Relaxed),
+            infos: counts[2].load(Ordering::Relaxed),
+            verbose: counts[3].load(Ordering::Relaxed)
+        }
+    }
+}
+
+//messages silenced by the allow and deny lists are not counted
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MessageCounts {
+    pub general: SeverityCounts,
+    pub validation: SeverityCounts,
+    pub performance: SeverityCounts
+}
+
+impl MessageCounts {
+    pub fn errors(&self) -> u64 {
+        self.general.errors + self.validation.errors + self.performance.errors
+    }
+
+    pub fn warnings(&self) -> u64 {
+        self.general.warnings + self.validation.warnings + self.performance.warnings
+    }
+}
+
+//output of debugPrintfEXT in a shader, stage and invocation are only known with verbose printf output
+#[derive(Clone, Debug)]
+pub struct ShaderPrintf {
+    pub stage: Option<vk::ShaderStageFlags>,
+    pub invocation: Option<String>,
+    pub message: String
+}
+
+impl ShaderPrintf {
+    fn is_printf(id_name: Option<&str>) -> bool {
+        id_name.is_some_and(|name| name.contains("DEBUG-PRINTF"))
+    }
+
+    //verbose messages describe the invocation in sentences, the printf output follows on the next lines:
+    //"... Stage = Fragment.  Fragment coord (x,y) = (10.5, 20.5). ...\nx = 1.0\ny = 2.0"
+    fn parse(text: &str) -> Self {
+        let described = text.find("Stage = ").map(|start| {
+            let message = text[start..].find('\n').map_or(text, |end| &text[start + end + 1..]);
+
+            let rest = &text[start + "Stage = ".len()..];
+            let stage_end = rest.find('.').unwrap_or(rest.len());
+            let stage = stage_name(&rest[..stage_end]);
+
+            let rest = rest[stage_end..].trim_start_matches('.').trim_start();
+            let invocation_end = [rest.find(". "), rest.find('\n')].into_iter()
+                .flatten()
+                .min()
+                .unwrap_or(rest.len());
+            let invocation = rest[..invocation_end].trim_end_matches('.').trim();
+
+            (stage, (!invocation.is_empty()).then(|| invocation.to_owned()), message)
+        });
+
+        match described {
+            Some((stage, invocation, message)) => Self {
+                stage,
+                invocation,
+                message: message.trim().to_owned()
+            },
+            None => Self {
+                stage: None,
+                invocation: None,
+                message: text.trim().to_owned()
+            },
+        }
+    }
+}
 
-        let messenger_info = vk::DebugUtilsMessengerCreateInfoEXT {
-            message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                //| vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+impl std::fmt::Display for ShaderPrintf {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(stage) = self.stage {
+            write!(f, "[{:?}] ", stage)?;
+        }
+        if let Some(invocation) = &self.invocation {
+            write!(f, "[{}] ", invocation)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+fn stage_name(name: &str) -> Option<vk::ShaderStageFlags> {
+    match name.trim() {
+        "Vertex" => Some(vk::ShaderStageFlags::VERTEX),
+        "TessellationControl" | "Tessellation Control" => Some(vk::ShaderStageFlags::TESSELLATION_CONTROL),
+        "TessellationEvaluation" | "Tessellation Evaluation" => Some(vk::ShaderStageFlags::TESSELLATION_EVALUATION),
+        "Geometry" => Some(vk::ShaderStageFlags::GEOMETRY),
+        "Fragment" => Some(vk::ShaderStageFlags::FRAGMENT),
+        "Compute" => Some(vk::ShaderStageFlags::COMPUTE),
+        "Task" => Some(vk::ShaderStageFlags::TASK_NV),
+        "Mesh" => Some(vk::ShaderStageFlags::MESH_NV),
+        _ => None,
+    }
+}
+
+//shared with the callback through its user data, the instance keeps it alive until it is destroyed
+pub struct MessengerState {
+    filter: RwLock<MessageFilter>,
+    error_action: RwLock<ErrorAction>,
+    //by type, then by severity
+    counts: [[AtomicU64; 4]; 3],
+    first_error: Mutex<Option<String>>,
+    printf: Mutex<VecDeque<ShaderPrintf>>
+}
+
+impl MessengerState {
+    pub fn new(filter: MessageFilter, error_action: ErrorAction) -> Self {
+        Self {
+            filter: RwLock::new(filter),
+            error_action: RwLock::new(error_action),
+            counts: Default::default(),
+            first_error: Mutex::new(None),
+            printf: Mutex::new(VecDeque::new())
+        }
+    }
+
+    //everything is requested, the filter decides at runtime
+    pub fn messenger_info(&self) -> vk::DebugUtilsMessengerCreateInfoEXT {
+        vk::DebugUtilsMessengerCreateInfoEXT {
+            message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+                | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
                 | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
             message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
                 | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
                 | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
             pfn_user_callback: Some(vulkan_debug_utils_callback),
+            p_user_data: self as *const Self as *mut ffi::c_void,
             ..Default::default()
+        }
+    }
+
+    pub fn first_error(&self) -> Option<String> {
+        self.first_error.lock().unwrap().clone()
+    }
+
+    pub fn assert_clean(&self) {
+        if let Some(error) = self.first_error() {
+            panic!("vulkan reported an error: {}", error);
+        }
+    }
+
+    //called after every frame, panics in panic mode once an error came in
+    pub fn check(&self) {
+        if *self.error_action.read().unwrap() == ErrorAction::Panic {
+            self.assert_clean();
+        }
+    }
+
+    pub fn counts(&self) -> MessageCounts {
+        MessageCounts {
+            general: SeverityCounts::new(&self.counts[0]),
+            validation: SeverityCounts::new(&self.counts[1]),
+            performance: SeverityCounts::new(&self.counts[2])
+        }
+    }
+
+    fn count(&self, severity: vk::DebugUtilsMessageSeverityFlagsEXT, ty: vk::DebugUtilsMessageTypeFlagsEXT) {
+        let ty = if ty.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+            1
+        } else if ty.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE) {
+            2
+        } else {
+            0
         };
+        let severity = match severity {
+            vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => 0,
+            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => 1,
+            vk::DebugUtilsMessageSeverityFlagsEXT::INFO => 2,
+            _ => 3,
+        };
+
+        self.counts[ty][severity].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub struct Debug {
+    //keeps the instance alive until the messenger is destroyed
+    _instance: Arc<Instance>,
+    loader: ext::DebugUtils,
+    messenger: vk::DebugUtilsMessengerEXT,
+    pub state: Arc<MessengerState>
+}
+
+impl Debug {
+    //the state should be the one chained into the instance, so both messengers share filters and counts
+    pub fn new(instance: &Arc<Instance>, state: &Arc<MessengerState>) -> Self {
+        let loader = ext::DebugUtils::new(&instance.entry, &instance.handle);
+
+        let messenger_info = state.messenger_info();
 
         let messenger = unsafe {
             loader.create_debug_utils_messenger(&messenger_info, None).unwrap()
         };
 
         Self {
+            _instance: instance.clone(),
             loader,
-            messenger
+            messenger,
+            state: state.clone()
+        }
+    }
+
+    pub fn filter(&self) -> MessageFilter {
+        self.state.filter.read().unwrap().clone()
+    }
+
+    pub fn set_filter(&self, filter: MessageFilter) {
+        *self.state.filter.write().unwrap() = filter;
+    }
+
+    pub fn set_severities(&self, severities: vk::DebugUtilsMessageSeverityFlagsEXT) {
+        self.state.filter.write().unwrap().severities = severities;
+    }
+
+    pub fn set_types(&self, types: vk::DebugUtilsMessageTypeFlagsEXT) {
+        self.state.filter.write().unwrap().types = types;
+    }
+
+    pub fn allow(&self, id: MessageId) {
+        self.state.filter.write().unwrap().allowed.push(id);
+    }
+
+    pub fn deny(&self, id: MessageId) {
+        self.state.filter.write().unwrap().denied.push(id);
+    }
+
+    pub fn set_error_action(&self, action: ErrorAction) {
+        *self.state.error_action.write().unwrap() = action;
+    }
+
+    pub fn counts(&self) -> MessageCounts {
+        self.state.counts()
+    }
+
+    pub fn reset_counts(&self) {
+        for count in self.state.counts.iter().flatten() {
+            count.store(0, Ordering::Relaxed);
+        }
+        *self.state.first_error.lock().unwrap() = None;
+    }
+
+    pub fn first_error(&self) -> Option<String> {
+        self.state.first_error()
+    }
+
+    pub fn assert_clean(&self) {
+        self.state.assert_clean();
+    }
+
+    //shader printf output received since the last call, oldest first
+    pub fn drain_printf(&self) -> Vec<ShaderPrintf> {
+        self.state.printf.lock().unwrap().drain(..).collect()
+    }
+
+    pub fn set_object_name<H: vk::Handle>(&self, device: vk::Device, handle: H, name: &str) {
+        let name = label_name(name);
+        let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(&name);
+
+        //names are only a debugging aid, failing to set one shouldn't take the renderer down
+        if let Err(error) = unsafe { self.loader.debug_utils_set_object_name(device, &info) } {
+            log::warn!("failed to name {:?} {:?}: {}", H::TYPE, name, error);
+        }
+    }
+
+    //regions show up in captures and in validation messages about commands inside them
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        let name = label_name(name);
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(&name);
+
+        unsafe {
+            self.loader.cmd_begin_debug_utils_label(command_buffer, &label);
+        }
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.loader.cmd_end_debug_utils_label(command_buffer);
+        }
+    }
+
+    pub fn insert_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        let name = label_name(name);
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(&name);
+
+        unsafe {
+            self.loader.cmd_insert_debug_utils_label(command_buffer, &label);
         }
     }
+}
 
-    pub unsafe fn cleanup(&mut self) {
-        self.loader.destroy_debug_utils_messenger(self.messenger, None);
+fn label_name(name: &str) -> ffi::CString {
+    ffi::CString::new(name.replace('\0', "")).unwrap()
+}
+
+impl Drop for Debug {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_debug_utils_messenger(self.messenger, None);
+        }
+    }
+}
+
+//dropped after the instance, so errors from destroying it are included
+impl Drop for MessengerState {
+    fn drop(&mut self) {
+        if *self.error_action.read().unwrap() != ErrorAction::Log && !std::thread::panicking() {
+            self.assert_clean();
+        }
+    }
+}
+
+unsafe fn c_str<'a>(ptr: *const ffi::c_char) -> Option<std::borrow::Cow<'a, str>> {
+    (!ptr.is_null()).then(|| ffi::CStr::from_ptr(ptr).to_string_lossy())
+}
+
+unsafe fn slice<'a, T>(ptr: *const T, count: u32) -> &'a [T] {
+    if ptr.is_null() { &[] } else { std::slice::from_raw_parts(ptr, count as usize) }
+}
+
+fn level(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> log::Level {
+    match severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => log::Level::Error,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => log::Level::Warn,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => log::Level::Info,
+        _ => log::Level::Debug,
+    }
+}
+
+fn target(ty: vk::DebugUtilsMessageTypeFlagsEXT) -> &'static str {
+    if ty.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+        "vulkan::validation"
+    } else if ty.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE) {
+        "vulkan::performance"
+    } else {
+        "vulkan::general"
     }
 }
 
@@ -40,13 +442,219 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut ffi::c_void,
+    p_user_data: *mut ffi::c_void,
 ) -> vk::Bool32 {
-    let message = ffi::CStr::from_ptr((*p_callback_data).p_message);
-    let severity = format!("{:?}", message_severity).to_lowercase();
-    let ty = format!("{:?}", message_type).to_lowercase();
+    let data = &*p_callback_data;
+    let id_name = c_str(data.p_message_id_name);
+
+    let state = match (p_user_data as *const MessengerState).as_ref() {
+        Some(state) => state,
+        None => return vk::FALSE,
+    };
+
+    //printf output is not a validation message, it bypasses filters and counts
+    if ShaderPrintf::is_printf(id_name.as_deref()) {
+        let printf = ShaderPrintf::parse(&c_str(data.p_message).unwrap_or_default());
+        log::info!(target: "vulkan::printf", "{}", printf);
+
+        let mut queue = state.printf.lock().unwrap();
+        if queue.len() == MAX_PRINTF_MESSAGES {
+            queue.pop_front();
+        }
+        queue.push_back(printf);
+
+        return vk::FALSE;
+    }
+
+    let filter = state.filter.read().unwrap();
+    if !filter.is_listed(id_name.as_deref(), data.message_id_number) {
+        return vk::FALSE;
+    }
+    state.count(message_severity, message_type);
+
+    let is_error = message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    let error_action = *state.error_action.read().unwrap();
+
+    let level = level(message_severity);
+    let target = target(message_type);
+    let logged = filter.shows(message_severity, message_type) && log::log_enabled!(target: target, level);
+    let recorded = is_error && error_action != ErrorAction::Log;
+    if !(logged || recorded) {
+        return vk::FALSE;
+    }
+
+    let mut text = format!("[{} {:#x}] {}",
+        id_name.as_deref().unwrap_or("-"),
+        data.message_id_number,
+        c_str(data.p_message).as_deref().unwrap_or(""));
+
+    for object in slice(data.p_objects, data.object_count) {
+        let _ = write!(text, "\n    object {:?} {:#x}", object.object_type, object.object_handle);
+        if let Some(name) = c_str(object.p_object_name) {
+            let _ = write!(text, " \"{}\"", name);
+        }
+    }
+
+    let labels = |labels: &[vk::DebugUtilsLabelEXT]| labels.iter()
+        .filter_map(|label| c_str(label.p_label_name))
+        .collect::<Vec<_>>()
+        .join(" > ");
+
+    let queue_labels = slice(data.p_queue_labels, data.queue_label_count);
+    if !queue_labels.is_empty() {
+        let _ = write!(text, "\n    queue labels: {}", labels(queue_labels));
+    }
+    let command_buffer_labels = slice(data.p_cmd_buf_labels, data.cmd_buf_label_count);
+    if !command_buffer_labels.is_empty() {
+        let _ = write!(text, "\n    command buffer labels: {}", labels(command_buffer_labels));
+    }
+
+    if logged {
+        log::log!(target: target, level, "{}", text);
+    }
 
-    println!("[Debug][{}][{}] {:?}", severity, ty, message);
+    if recorded {
+        state.first_error.lock().unwrap().get_or_insert(text);
+    }
 
     vk::FALSE
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use vk::DebugUtilsMessageSeverityFlagsEXT as Severity;
+    use vk::DebugUtilsMessageTypeFlagsEXT as Type;
+
+    fn send(state: &MessengerState, severity: Severity, ty: Type, id: &str, message: &str) {
+        let id = ffi::CString::new(id).unwrap();
+        let message = ffi::CString::new(message).unwrap();
+        let data = vk::DebugUtilsMessengerCallbackDataEXT {
+            p_message_id_name: id.as_ptr(),
+            p_message: message.as_ptr(),
+            ..Default::default()
+        };
+
+        let result = unsafe {
+            vulkan_debug_utils_callback(severity, ty, &data, state as *const MessengerState as *mut ffi::c_void)
+        };
+        assert_eq!(result, vk::FALSE);
+    }
+
+    //dropping a state with an error panics outside of log mode
+    fn forget_errors(state: &MessengerState) {
+        *state.error_action.write().unwrap() = ErrorAction::Log;
+    }
+
+    #[test]
+    fn fail_mode_keeps_the_first_error() {
+        let state = MessengerState::new(MessageFilter::default(), ErrorAction::Fail);
+
+        send(&state, Severity::WARNING, Type::VALIDATION, "VUID-warning", "not an error");
+        assert_eq!(state.first_error(), None);
+
+        send(&state, Severity::ERROR, Type::VALIDATION, "VUID-first", "first error");
+        send(&state, Severity::ERROR, Type::VALIDATION, "VUID-second", "second error");
+
+        let error = state.first_error().unwrap();
+        assert!(error.contains("VUID-first") && error.contains("first error"));
+        assert!(std::panic::catch_unwind(|| state.assert_clean()).is_err());
+
+        //fail mode only panics when asked to
+        state.check();
+        forget_errors(&state);
+    }
+
+    #[test]
+    fn log_mode_keeps_no_errors() {
+        let state = MessengerState::new(MessageFilter::default(), ErrorAction::Log);
+
+        send(&state, Severity::ERROR, Type::VALIDATION, "VUID-error", "error");
+
+        assert_eq!(state.first_error(), None);
+        state.assert_clean();
+        assert_eq!(state.counts().validation.errors, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "VUID-error")]
+    fn panic_mode_panics_after_the_callback() {
+        let state = MessengerState::new(MessageFilter::default(), ErrorAction::Panic);
+
+        send(&state, Severity::ERROR, Type::VALIDATION, "VUID-error", "error");
+
+        state.check();
+    }
+
+    #[test]
+    fn counts_are_split_by_type_and_severity() {
+        let filter = MessageFilter {
+            denied: vec![MessageId::Name("VUID-denied".to_owned())],
+            ..Default::default()
+        };
+        let state = MessengerState::new(filter, ErrorAction::Fail);
+
+        send(&state, Severity::ERROR, Type::VALIDATION, "VUID-error", "error");
+        send(&state, Severity::WARNING, Type::VALIDATION, "VUID-warning", "warning");
+        send(&state, Severity::WARNING, Type::PERFORMANCE, "perf", "slow");
+        send(&state, Severity::INFO, Type::GENERAL, "loader", "info");
+        send(&state, Severity::VERBOSE, Type::GENERAL, "loader", "verbose");
+        //silenced messages are neither counted nor remembered
+        send(&state, Severity::ERROR, Type::VALIDATION, "VUID-denied", "denied");
+
+        let counts = state.counts();
+        assert_eq!(counts.validation.errors, 1);
+        assert_eq!(counts.validation.warnings, 1);
+        assert_eq!(counts.performance.warnings, 1);
+        assert_eq!(counts.general.infos, 1);
+        assert_eq!(counts.general.verbose, 1);
+        assert_eq!(counts.errors(), 1);
+        assert_eq!(counts.warnings(), 2);
+        assert!(state.first_error().unwrap().contains("VUID-error"));
+
+        forget_errors(&state);
+    }
+
+    #[test]
+    fn verbose_printf_keeps_every_line_of_output() {
+        let printf = ShaderPrintf::parse(
+            "Validation Information: [ WARNING-DEBUG-PRINTF ] | MessageID = 0x76589099 | Command buffer (0x1). \
+            Draw Index 0. Shader Instruction Index = 92.  Stage = Fragment.  Fragment coord (x,y) = (10.5, 20.5).  \
+            Unable to find SPIR-V OpLine for source information.\ncolor = 1.0\nalpha = 0.5\n");
+
+        assert_eq!(printf.stage, Some(vk::ShaderStageFlags::FRAGMENT));
+        assert_eq!(printf.invocation.as_deref(), Some("Fragment coord (x,y) = (10.5, 20.5)"));
+        assert_eq!(printf.message, "color = 1.0\nalpha = 0.5");
+    }
+
+    #[test]
+    fn verbose_printf_ends_the_invocation_at_the_line_break() {
+        let printf = ShaderPrintf::parse("Stage = Compute.  Global invocation ID (x, y, z) = (3, 0, 0)\nid = 3");
+
+        assert_eq!(printf.stage, Some(vk::ShaderStageFlags::COMPUTE));
+        assert_eq!(printf.invocation.as_deref(), Some("Global invocation ID (x, y, z) = (3, 0, 0)"));
+        assert_eq!(printf.message, "id = 3");
+    }
+
+    #[test]
+    fn plain_printf_is_the_whole_message() {
+        let printf = ShaderPrintf::parse("x = 1.0\ny = 2.0\n");
+
+        assert_eq!(printf.stage, None);
+        assert_eq!(printf.invocation, None);
+        assert_eq!(printf.message, "x = 1.0\ny = 2.0");
+    }
+
+    #[test]
+    fn printf_is_queued_and_not_counted() {
+        let state = MessengerState::new(MessageFilter::default(), ErrorAction::Fail);
+
+        send(&state, Severity::INFO, Type::VALIDATION, "WARNING-DEBUG-PRINTF", "first");
+        send(&state, Severity::INFO, Type::VALIDATION, "WARNING-DEBUG-PRINTF", "second");
+
+        let queued: Vec<String> = state.printf.lock().unwrap().drain(..).map(|printf| printf.message).collect();
+        assert_eq!(queued, ["first", "second"]);
+        assert_eq!(state.counts().validation.infos, 0);
+    }
+}
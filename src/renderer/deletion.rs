@@ -0,0 +1,174 @@
+use super::{Buffer, Device};
+use super::texture::Texture;
+use super::mesh::Mesh;
+use super::pipeline::{Pipeline, ComputePipeline};
+use super::timeline::Timeline;
+use super::swapchain::Swapchain;
+use super::graph::RenderGraph;
+use super::profiler::GpuProfiler;
+use super::viewport::Viewport;
+use super::scene::Scene;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocation;
+use std::{iter, sync::Arc};
+
+pub enum Retired {
+    Buffer(Buffer),
+    Texture(Texture),
+    Mesh(Mesh),
+    Pipeline(Pipeline),
+    ComputePipeline(ComputePipeline),
+    Swapchain(Swapchain),
+    RenderGraph(RenderGraph),
+    Profiler(GpuProfiler),
+    Viewport(Box<Viewport>),
+    Scene(Scene),
+    Image(vk::Image, Allocation),
+    ImageView(vk::ImageView),
+    Sampler(vk::Sampler),
+    Framebuffer(vk::Framebuffer),
+    RenderPass(vk::RenderPass),
+    DescriptorPool(vk::DescriptorPool),
+    CommandBuffers(vk::CommandPool, Vec<vk::CommandBuffer>)
+}
+
+impl Retired {
+    //wrapper types destroy themselves when dropped, raw handles are destroyed here
+    unsafe fn destroy(self, device: &Device) {
+        match self {
+            Retired::Buffer(_)
+            | Retired::Texture(_)
+            | Retired::Mesh(_)
+            | Retired::Pipeline(_)
+            | Retired::ComputePipeline(_)
+            | Retired::Swapchain(_)
+            | Retired::RenderGraph(_)
+            | Retired::Profiler(_)
+            | Retired::Viewport(_)
+            | Retired::Scene(_) => {},
+            Retired::Image(image, allocation) => {
+                device.logical.destroy_image(image, None);
+                device.allocator.lock().unwrap().free(allocation).unwrap();
+            },
+            Retired::ImageView(view) => device.logical.destroy_image_view(view, None),
+            Retired::Sampler(sampler) => device.logical.destroy_sampler(sampler, None),
+            Retired::Framebuffer(framebuffer) => device.logical.destroy_framebuffer(framebuffer, None),
+            Retired::RenderPass(render_pass) => device.logical.destroy_render_pass(render_pass, None),
+            Retired::DescriptorPool(pool) => device.logical.destroy_descriptor_pool(pool, None),
+            Retired::CommandBuffers(pool, command_buffers) => device.logical.free_command_buffers(pool, &command_buffers),
+        }
+    }
+}
+
+//resources wait here until every timeline they were used on passes the value of the last submission using them
+pub struct DeletionQueue {
+    pub device: Arc<Device>,
+    pending: Vec<(Vec<(vk::Semaphore, u64)>, Retired)>
+}
+
+impl DeletionQueue {
+    pub fn new(device: &Arc<Device>) -> Self {
+        Self {
+            device: device.clone(),
+            pending: vec![]
+        }
+    }
+
+    fn timelines(device: &Device) -> impl Iterator<Item = &Timeline> {
+        iter::once(&device.graphics_timeline).chain(&device.compute_timeline)
+    }
+
+    //retires after everything submitted so far, on any queue, has finished
+    pub fn push(&mut self, resource: Retired) {
+        let waits = Self::timelines(&self.device)
+            .map(|timeline| (timeline.semaphore, timeline.last_submitted()))
+            .collect();
+        self.pending.push((waits, resource));
+    }
+
+    //for resources only used on one timeline
+    pub fn push_at(&mut self, timeline: &Timeline, value: u64, resource: Retired) {
+        self.pending.push((vec![(timeline.semaphore, value)], resource));
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn collect(&mut self) {
+        let completed: Vec<(vk::Semaphore, u64)> = Self::timelines(&self.device)
+            .map(|timeline| (timeline.semaphore, timeline.completed(&self.device.logical)))
+            .collect();
+
+        for resource in take_completed(&mut self.pending, &completed) {
+            unsafe {
+                resource.destroy(&self.device);
+            }
+        }
+    }
+}
+
+//waits for everything submitted so far, so whatever is left is no longer in use
+impl Drop for DeletionQueue {
+    fn drop(&mut self) {
+        for timeline in Self::timelines(&self.device) {
+            timeline.wait_idle(&self.device.logical);
+        }
+
+        for (_, resource) in self.pending.drain(..) {
+            unsafe {
+                resource.destroy(&self.device);
+            }
+        }
+    }
+}
+
+//removes the entries whose waits all passed, given the completed value of each timeline
+fn take_completed<T>(pending: &mut Vec<(Vec<(vk::Semaphore, u64)>, T)>, completed: &[(vk::Semaphore, u64)]) -> Vec<T> {
+    let is_complete = |semaphore: vk::Semaphore, value: u64| completed.iter()
+        .any(|&(completed, completed_value)| completed == semaphore && completed_value >= value);
+
+    let (done, still_pending): (Vec<_>, Vec<_>) = pending.drain(..)
+        .partition(|(waits, _)| waits.iter().all(|&(semaphore, value)| is_complete(semaphore, value)));
+    *pending = still_pending;
+
+    done.into_iter().map(|(_, resource)| resource).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn retired_resources_wait_for_their_timeline_value() {
+        let graphics = vk::Semaphore::from_raw(1);
+        let compute = vk::Semaphore::from_raw(2);
+
+        let mut pending = vec![
+            (vec![(graphics, 3)], "swapchain"),
+            (vec![(graphics, 5)], "graph"),
+            (vec![(graphics, 2), (compute, 4)], "buffer")
+        ];
+
+        let done = take_completed(&mut pending, &[(graphics, 2), (compute, 0)]);
+        assert!(done.is_empty());
+        assert_eq!(pending.len(), 3);
+
+        let done = take_completed(&mut pending, &[(graphics, 3), (compute, 0)]);
+        assert_eq!(done, ["swapchain"]);
+
+        //every timeline it was used on has to pass its value
+        let done = take_completed(&mut pending, &[(graphics, 4), (compute, 4)]);
+        assert_eq!(done, ["buffer"]);
+
+        let done = take_completed(&mut pending, &[(graphics, 5), (compute, 4)]);
+        assert_eq!(done, ["graph"]);
+        assert!(pending.is_empty());
+    }
+}
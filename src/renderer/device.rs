@@ -1,51 +1,454 @@
+use super::instance::Instance;
+use super::debug::Debug;
+use super::timeline::{Timeline, SemaphoreWait};
+
 use ash::{vk, extensions::khr};
+use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
+use std::{ffi, mem::ManuallyDrop, path::PathBuf, sync::{Arc, Mutex}};
+
+const PIPELINE_CACHE_HEADER_SIZE: usize = 32;
 
 pub struct QueueFamily {
     pub index: u32,
     pub flags: vk::QueueFlags,
+    pub timestamp_valid_bits: u32,
     pub queues: Vec<vk::Queue>
 }
 
+//command buffers allocated from it are freed along with it
+pub struct CommandPool {
+    pub device: Arc<Device>,
+    pub pool: vk::CommandPool
+}
+
+impl CommandPool {
+    pub fn new(device: &Arc<Device>, queue_family_index: u32, flags: vk::CommandPoolCreateFlags, name: &str) -> Self {
+        let info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(flags);
+
+        let pool = unsafe {
+            device.logical.create_command_pool(&info, None).unwrap()
+        };
+        device.set_object_name(pool, name);
+
+        Self {
+            device: device.clone(),
+            pool
+        }
+    }
+}
+
+impl Drop for CommandPool {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.logical.destroy_command_pool(self.pool, None);
+        }
+    }
+}
+
 pub struct Device {
+    pub instance: Arc<Instance>,
+    pub debug: Option<Arc<Debug>>,
     pub physical: vk::PhysicalDevice,
     pub logical: ash::Device,
-    pub graphics_family: QueueFamily
+    pub graphics_family: QueueFamily,
+    pub compute_family: Option<QueueFamily>,
+    pub features: vk::PhysicalDeviceFeatures,
+    pub allocator: ManuallyDrop<Mutex<Allocator>>,
+    pub transient_pool: vk::CommandPool,
+    pub compute_pool: vk::CommandPool,
+    pub pipeline_cache: vk::PipelineCache,
+    pub dynamic_rendering: Option<khr::DynamicRendering>,
+    //of every depth buffer, with depth only layouts
+    pub depth_format: vk::Format,
+    pub synchronization2: Option<khr::Synchronization2>,
+    pub graphics_timeline: Timeline,
+    pub compute_timeline: Option<Timeline>
 }
 
 impl Device {
-    pub fn new(instance: &ash::Instance, layer_names: &Vec<*const i8>) -> Self {
-        let extension_names = vec![khr::Swapchain::name().as_ptr()];
-        let physical = Self::pick_physical(&instance);
+    pub fn new(instance: &Arc<Instance>, debug: Option<Arc<Debug>>, layer_names: &[*const i8]) -> Self {
+        let handle = &instance.handle;
+        let mut extension_names = vec![khr::Swapchain::name().as_ptr()];
+        let physical = Self::pick_physical(handle);
+
+        let supports_dynamic_rendering = Self::supports_dynamic_rendering(handle, physical);
+        if supports_dynamic_rendering {
+            extension_names.push(khr::DynamicRendering::name().as_ptr());
+        }
+        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::builder()
+            .dynamic_rendering(true);
+
+        let supports_synchronization2 = Self::supports_synchronization2(handle, physical);
+        if supports_synchronization2 {
+            extension_names.push(khr::Synchronization2::name().as_ptr());
+        }
+        let mut synchronization2_features = vk::PhysicalDeviceSynchronization2Features::builder()
+            .synchronization2(true);
 
-        let mut graphics_family = Self::pick_queue_family(&instance, physical);
+        //timeline semaphores are core since 1.2 but still have to be enabled
+        let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::builder()
+            .timeline_semaphore(true);
+
+        //depth attachments are transitioned to the depth only layouts, core since 1.2
+        let mut depth_layout_features = vk::PhysicalDeviceSeparateDepthStencilLayoutsFeatures::builder()
+            .separate_depth_stencil_layouts(true);
+
+        let mut graphics_family = Self::pick_queue_family(handle, physical);
+        let mut compute_family = Self::pick_compute_family(handle, physical);
+
+        let features = Self::pick_features(handle, physical);
 
         let queue_priorities = [1.0];
 
-        let queue_infos = vec![
+        let mut queue_infos = vec![
             vk::DeviceQueueCreateInfo::builder()
                 .queue_family_index(graphics_family.index)
                 .queue_priorities(&queue_priorities)
                 .build()
         ];
+        if let Some(compute_family) = &compute_family {
+            queue_infos.push(vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(compute_family.index)
+                .queue_priorities(&queue_priorities)
+                .build());
+        }
 
-        let logical_device_info = vk::DeviceCreateInfo::builder()
+        let mut logical_device_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_infos)
             .enabled_extension_names(&extension_names)
-            .enabled_layer_names(&layer_names);
+            .enabled_layer_names(layer_names)
+            .enabled_features(&features)
+            .push_next(&mut timeline_features)
+            .push_next(&mut depth_layout_features);
+        if supports_dynamic_rendering {
+            logical_device_info = logical_device_info.push_next(&mut dynamic_rendering_features);
+        }
+        if supports_synchronization2 {
+            logical_device_info = logical_device_info.push_next(&mut synchronization2_features);
+        }
 
         let logical = unsafe {
-            instance.create_device(physical, &logical_device_info, None).unwrap()
+            handle.create_device(physical, &logical_device_info, None).unwrap()
         };
 
         graphics_family.queues.push(unsafe {
             logical.get_device_queue(graphics_family.index, 0)
         });
+        if let Some(compute_family) = &mut compute_family {
+            compute_family.queues.push(unsafe {
+                logical.get_device_queue(compute_family.index, 0)
+            });
+        }
 
-        Self {
+        let allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: handle.clone(),
+            device: logical.clone(),
+            physical_device: physical,
+            debug_settings: Default::default(),
+            buffer_device_address: false
+        }).unwrap();
+
+        let transient_pool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(graphics_family.index)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+
+        let transient_pool = unsafe {
+            logical.create_command_pool(&transient_pool_info, None).unwrap()
+        };
+
+        let compute_pool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(compute_family.as_ref().unwrap_or(&graphics_family).index)
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+
+        let compute_pool = unsafe {
+            logical.create_command_pool(&compute_pool_info, None).unwrap()
+        };
+
+        let pipeline_cache = Self::new_pipeline_cache(handle, physical, &logical);
+
+        let dynamic_rendering = if supports_dynamic_rendering {
+            Some(khr::DynamicRendering::new(handle, &logical))
+        } else {
+            None
+        };
+
+        let depth_format = Self::pick_depth_format(handle, physical);
+
+        let synchronization2 = if supports_synchronization2 {
+            Some(khr::Synchronization2::new(handle, &logical))
+        } else {
+            None
+        };
+
+        let graphics_timeline = Timeline::new(&logical);
+        let compute_timeline = compute_family.as_ref().map(|_| Timeline::new(&logical));
+
+        let device = Self {
+            instance: instance.clone(),
+            debug,
             physical,
             logical,
-            graphics_family
+            graphics_family,
+            compute_family,
+            features,
+            allocator: ManuallyDrop::new(Mutex::new(allocator)),
+            transient_pool,
+            compute_pool,
+            pipeline_cache,
+            dynamic_rendering,
+            depth_format,
+            synchronization2,
+            graphics_timeline,
+            compute_timeline
+        };
+        device.name_objects();
+        device
+    }
+
+    fn name_objects(&self) {
+        self.set_object_name(self.logical.handle(), "device");
+        self.set_object_name(self.graphics_family.queues[0], "graphics queue");
+        if let Some(compute_family) = &self.compute_family {
+            self.set_object_name(compute_family.queues[0], "compute queue");
+        }
+        self.set_object_name(self.transient_pool, "transient pool");
+        self.set_object_name(self.compute_pool, "compute pool");
+        self.set_object_name(self.pipeline_cache, "pipeline cache");
+        self.set_object_name(self.graphics_timeline.semaphore, "graphics timeline");
+        if let Some(timeline) = &self.compute_timeline {
+            self.set_object_name(timeline.semaphore, "compute timeline");
+        }
+    }
+
+    //no-ops without debug utils
+    pub fn set_object_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        if let Some(debug) = &self.debug {
+            debug.set_object_name(self.logical.handle(), handle, name);
+        }
+    }
+
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        if let Some(debug) = &self.debug {
+            debug.begin_label(command_buffer, name);
+        }
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug) = &self.debug {
+            debug.end_label(command_buffer);
+        }
+    }
+
+    fn supports_extension(instance: &ash::Instance, physical_device: vk::PhysicalDevice, name: &ffi::CStr) -> bool {
+        let extensions = unsafe {
+            instance.enumerate_device_extension_properties(physical_device).unwrap()
+        };
+
+        extensions.iter().any(|extension| unsafe {
+            ffi::CStr::from_ptr(extension.extension_name.as_ptr()) == name
+        })
+    }
+
+    fn supports_dynamic_rendering(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+        if !Self::supports_extension(instance, physical_device, khr::DynamicRendering::name()) {
+            return false;
+        }
+
+        let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut dynamic_rendering);
+
+        unsafe {
+            instance.get_physical_device_features2(physical_device, &mut features);
+        }
+
+        dynamic_rendering.dynamic_rendering == vk::TRUE
+    }
+
+    fn supports_synchronization2(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+        if !Self::supports_extension(instance, physical_device, khr::Synchronization2::name()) {
+            return false;
+        }
+
+        let mut synchronization2 = vk::PhysicalDeviceSynchronization2Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut synchronization2);
+
+        unsafe {
+            instance.get_physical_device_features2(physical_device, &mut features);
+        }
+
+        synchronization2.synchronization2 == vk::TRUE
+    }
+
+    fn pick_features(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> vk::PhysicalDeviceFeatures {
+        let supported = unsafe {
+            instance.get_physical_device_features(physical_device)
+        };
+
+        vk::PhysicalDeviceFeatures {
+            texture_compression_bc: supported.texture_compression_bc,
+            texture_compression_etc2: supported.texture_compression_etc2,
+            texture_compression_astc_ldr: supported.texture_compression_astc_ldr,
+            pipeline_statistics_query: supported.pipeline_statistics_query,
+            ..Default::default()
+        }
+    }
+
+    //d16 is supported everywhere, the others are more precise
+    fn pick_depth_format(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> vk::Format {
+        [vk::Format::D32_SFLOAT, vk::Format::X8_D24_UNORM_PACK32, vk::Format::D16_UNORM]
+            .into_iter()
+            .find(|&format| {
+                let props = unsafe {
+                    instance.get_physical_device_format_properties(physical_device, format)
+                };
+                props.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .unwrap()
+    }
+
+    fn pipeline_cache_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("lve").join("pipeline_cache.bin"))
+    }
+
+    fn new_pipeline_cache(instance: &ash::Instance, physical_device: vk::PhysicalDevice, logical: &ash::Device) -> vk::PipelineCache {
+        let props = unsafe {
+            instance.get_physical_device_properties(physical_device)
+        };
+
+        let data = Self::pipeline_cache_path()
+            .and_then(|path| std::fs::read(path).ok())
+            .filter(|data| Self::is_pipeline_cache_valid(data, &props))
+            .unwrap_or_default();
+
+        let info = vk::PipelineCacheCreateInfo::builder()
+            .initial_data(&data);
+
+        unsafe {
+            logical.create_pipeline_cache(&info, None).unwrap()
+        }
+    }
+
+    //caches written by another gpu or driver are discarded
+    fn is_pipeline_cache_valid(data: &[u8], props: &vk::PhysicalDeviceProperties) -> bool {
+        if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+            return false;
         }
+
+        let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        read_u32(0) as usize >= PIPELINE_CACHE_HEADER_SIZE
+            && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && read_u32(8) == props.vendor_id
+            && read_u32(12) == props.device_id
+            && data[16..32] == props.pipeline_cache_uuid
+    }
+
+    fn save_pipeline_cache(&self) -> anyhow::Result<()> {
+        let path = Self::pipeline_cache_path()
+            .ok_or_else(|| anyhow::anyhow!("no user cache directory"))?;
+
+        let data = unsafe {
+            self.logical.get_pipeline_cache_data(self.pipeline_cache)?
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    pub fn supports_format(&self, format: vk::Format, features: vk::FormatFeatureFlags) -> bool {
+        let props = unsafe {
+            self.instance.handle.get_physical_device_format_properties(self.physical, format)
+        };
+
+        props.optimal_tiling_features.contains(features)
+    }
+
+    pub fn submit_once(&self, record: impl FnOnce(vk::CommandBuffer)) {
+        let alloc_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(self.transient_pool)
+            .command_buffer_count(1);
+
+        let command_buffers = unsafe {
+            self.logical.allocate_command_buffers(&alloc_info).unwrap()
+        };
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            self.logical.begin_command_buffer(command_buffers[0], &begin_info).unwrap();
+        }
+
+        record(command_buffers[0]);
+
+        unsafe {
+            self.logical.end_command_buffer(command_buffers[0]).unwrap();
+        }
+
+        let value = self.submit_graphics(&command_buffers, &[], &[]);
+        self.graphics_timeline.wait(&self.logical, value);
+
+        unsafe {
+            self.logical.free_command_buffers(self.transient_pool, &command_buffers);
+        }
+    }
+
+    //signals the next value of the queue's timeline and returns it
+    fn submit(
+        &self,
+        queue: vk::Queue,
+        timeline: &Timeline,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[SemaphoreWait],
+        signal_semaphores: &[vk::Semaphore])
+    -> u64 {
+        let value = timeline.next_value();
+
+        let wait_semaphores: Vec<_> = waits.iter().map(|wait| wait.semaphore).collect();
+        let wait_values: Vec<_> = waits.iter().map(|wait| wait.value).collect();
+        let wait_stages: Vec<_> = waits.iter().map(|wait| wait.stage).collect();
+
+        let mut signals = signal_semaphores.to_vec();
+        signals.push(timeline.semaphore);
+        let mut signal_values = vec![0; signal_semaphores.len()];
+        signal_values.push(value);
+
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+
+        let submit_info = [
+            vk::SubmitInfo::builder()
+                .wait_semaphores(&wait_semaphores)
+                .wait_dst_stage_mask(&wait_stages)
+                .command_buffers(command_buffers)
+                .signal_semaphores(&signals)
+                .push_next(&mut timeline_info)
+                .build()
+        ];
+
+        unsafe {
+            self.logical.queue_submit(queue, &submit_info, vk::Fence::null()).unwrap();
+        }
+
+        value
+    }
+
+    pub fn submit_graphics(
+        &self,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[SemaphoreWait],
+        signal_semaphores: &[vk::Semaphore])
+    -> u64 {
+        self.submit(self.graphics_family.queues[0], &self.graphics_timeline, command_buffers, waits, signal_semaphores)
     }
 
     fn pick_physical(instance: &ash::Instance) -> vk::PhysicalDevice {
@@ -76,6 +479,7 @@ impl Device {
                 return QueueFamily {
                     index: i as u32,
                     flags: qfp.queue_flags,
+                    timestamp_valid_bits: qfp.timestamp_valid_bits,
                     queues: vec![]
                 }
             }
@@ -84,7 +488,71 @@ impl Device {
         panic!("No graphics queue family found!");
     }
 
-    pub unsafe fn cleanup(&mut self) {
-        self.logical.destroy_device(None);
+    fn pick_compute_family(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Option<QueueFamily> {
+        let qfps = unsafe {
+            instance.get_physical_device_queue_family_properties(physical_device)
+        };
+
+        //async compute needs a family separate from graphics
+        qfps.iter()
+            .enumerate()
+            .find(|(_, qfp)| qfp.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !qfp.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .map(|(i, qfp)| QueueFamily {
+                index: i as u32,
+                flags: qfp.queue_flags,
+                timestamp_valid_bits: qfp.timestamp_valid_bits,
+                queues: vec![]
+            })
+    }
+
+    pub fn compute_queue(&self) -> (u32, vk::Queue) {
+        let family = self.compute_family.as_ref().unwrap_or(&self.graphics_family);
+        (family.index, family.queues[0])
+    }
+
+    pub fn queue_family_indices(&self) -> Vec<u32> {
+        let mut indices = vec![self.graphics_family.index];
+        if let Some(compute_family) = &self.compute_family {
+            indices.push(compute_family.index);
+        }
+        indices
+    }
+
+    //without a dedicated compute queue compute work shares the graphics timeline
+    pub fn compute_timeline(&self) -> &Timeline {
+        self.compute_timeline.as_ref().unwrap_or(&self.graphics_timeline)
+    }
+
+    pub fn submit_compute(
+        &self,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[SemaphoreWait],
+        signal_semaphores: &[vk::Semaphore])
+    -> u64 {
+        self.submit(self.compute_queue().1, self.compute_timeline(), command_buffers, waits, signal_semaphores)
+    }
+
+}
+
+//resources hold an Arc to the device, so it is only destroyed once all of them are gone
+impl Drop for Device {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_pipeline_cache() {
+            log::warn!("failed to save the pipeline cache: {}", e);
+        }
+
+        unsafe {
+            self.logical.destroy_pipeline_cache(self.pipeline_cache, None);
+
+            self.logical.destroy_command_pool(self.transient_pool, None);
+            self.logical.destroy_command_pool(self.compute_pool, None);
+            self.graphics_timeline.cleanup(&self.logical);
+            if let Some(timeline) = &mut self.compute_timeline {
+                timeline.cleanup(&self.logical);
+            }
+            ManuallyDrop::drop(&mut self.allocator);
+            self.logical.destroy_device(None);
+        }
     }
 }
@@ -0,0 +1,913 @@
+use super::{Buffer, Device};
+use super::sync::{ResourceTracker, ResourceState};
+use super::profiler::GpuProfiler;
+
+use ash::vk;
+use gpu_allocator::{MemoryLocation, vulkan::{Allocation, AllocationCreateDesc}};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ImageHandle(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BufferHandle(usize);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BufferDesc {
+    pub size: vk::DeviceSize,
+    pub usage: vk::BufferUsageFlags
+}
+
+enum ImageKind {
+    Transient(ImageDesc),
+    Imported {
+        initial: ResourceState,
+        final_state: Option<ResourceState>
+    }
+}
+
+struct GraphImage {
+    name: String,
+    kind: ImageKind,
+    image: vk::Image,
+    view: vk::ImageView,
+    range: vk::ImageSubresourceRange,
+    //previous image living in the same memory, whose last use the first use has to wait on
+    aliases: Option<usize>,
+    //the uses of its memory in the previous frame, the first use of a frame waits on them
+    frame_end: ResourceState
+}
+
+enum BufferKind {
+    Transient(BufferDesc),
+    Imported {
+        final_state: Option<ResourceState>
+    }
+}
+
+struct GraphBuffer {
+    name: String,
+    kind: BufferKind,
+    buffer: vk::Buffer,
+    owned: Option<Buffer>,
+    frame_end: ResourceState
+}
+
+#[derive(Clone, Copy)]
+enum Access {
+    Image(usize, ResourceState),
+    Buffer(usize, ResourceState)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Resource {
+    Image(usize),
+    Buffer(usize)
+}
+
+impl Access {
+    fn resource(&self) -> Resource {
+        match *self {
+            Access::Image(image, _) => Resource::Image(image),
+            Access::Buffer(buffer, _) => Resource::Buffer(buffer),
+        }
+    }
+}
+
+struct Pass {
+    name: String,
+    reads: Vec<Access>,
+    writes: Vec<Access>,
+    side_effects: bool,
+    execute: Box<dyn Fn(&PassContext)>
+}
+
+impl Pass {
+    fn reads(&self, resource: Resource) -> bool {
+        self.reads.iter().any(|access| access.resource() == resource)
+    }
+
+    fn writes(&self, resource: Resource) -> bool {
+        self.writes.iter().any(|access| access.resource() == resource)
+    }
+}
+
+pub struct PassBuilder<'a> {
+    pass: &'a mut Pass
+}
+
+impl PassBuilder<'_> {
+    pub fn read_image(&mut self, image: ImageHandle, state: ResourceState) -> &mut Self {
+        self.pass.reads.push(Access::Image(image.0, state));
+        self
+    }
+
+    pub fn write_image(&mut self, image: ImageHandle, state: ResourceState) -> &mut Self {
+        self.pass.writes.push(Access::Image(image.0, state));
+        self
+    }
+
+    pub fn read_buffer(&mut self, buffer: BufferHandle, state: ResourceState) -> &mut Self {
+        self.pass.reads.push(Access::Buffer(buffer.0, state));
+        self
+    }
+
+    pub fn write_buffer(&mut self, buffer: BufferHandle, state: ResourceState) -> &mut Self {
+        self.pass.writes.push(Access::Buffer(buffer.0, state));
+        self
+    }
+
+    //passes with side effects are never culled
+    pub fn side_effects(&mut self) -> &mut Self {
+        self.pass.side_effects = true;
+        self
+    }
+}
+
+pub struct PassContext<'a> {
+    pub device: &'a Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub frame_index: usize,
+    images: &'a [GraphImage],
+    buffers: &'a [GraphBuffer],
+    app: Option<&'a dyn Fn(&PassContext)>
+}
+
+impl PassContext<'_> {
+    //lets the application record its own commands into the pass
+    pub fn record_app(&self) {
+        if let Some(app) = self.app {
+            app(self);
+        }
+    }
+
+    pub fn image(&self, image: ImageHandle) -> vk::Image {
+        self.images[image.0].image
+    }
+
+    pub fn view(&self, image: ImageHandle) -> vk::ImageView {
+        self.images[image.0].view
+    }
+
+    pub fn extent(&self, image: ImageHandle) -> Option<vk::Extent2D> {
+        match self.images[image.0].kind {
+            ImageKind::Transient(desc) => Some(desc.extent),
+            ImageKind::Imported { .. } => None,
+        }
+    }
+
+    pub fn buffer(&self, buffer: BufferHandle) -> vk::Buffer {
+        self.buffers[buffer.0].buffer
+    }
+}
+
+#[derive(Default)]
+pub struct RenderGraph {
+    //set once compiled, transient resources are created from it
+    device: Option<Arc<Device>>,
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<Pass>,
+    order: Vec<usize>,
+    memory: Vec<Allocation>,
+    tracker: ResourceTracker
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageHandle {
+        self.images.push(GraphImage {
+            name: name.to_owned(),
+            kind: ImageKind::Transient(desc),
+            image: vk::Image::null(),
+            view: vk::ImageView::null(),
+            range: subresource_range(aspect_mask(desc.format)),
+            aliases: None,
+            frame_end: ResourceState::UNDEFINED
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        range: vk::ImageSubresourceRange,
+        initial: ResourceState,
+        final_state: Option<ResourceState>)
+    -> ImageHandle {
+        self.images.push(GraphImage {
+            name: name.to_owned(),
+            kind: ImageKind::Imported {
+                initial,
+                final_state
+            },
+            image: vk::Image::null(),
+            view: vk::ImageView::null(),
+            range,
+            aliases: None,
+            frame_end: ResourceState::UNDEFINED
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    pub fn set_image(&mut self, handle: ImageHandle, image: vk::Image, view: vk::ImageView) {
+        let graph_image = &mut self.images[handle.0];
+        assert!(matches!(graph_image.kind, ImageKind::Imported { .. }), "{} is not an imported image", graph_image.name);
+
+        graph_image.image = image;
+        graph_image.view = view;
+    }
+
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> BufferHandle {
+        self.buffers.push(GraphBuffer {
+            name: name.to_owned(),
+            kind: BufferKind::Transient(desc),
+            buffer: vk::Buffer::null(),
+            owned: None,
+            frame_end: ResourceState::UNDEFINED
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer, final_state: Option<ResourceState>) -> BufferHandle {
+        self.buffers.push(GraphBuffer {
+            name: name.to_owned(),
+            kind: BufferKind::Imported {
+                final_state
+            },
+            buffer,
+            owned: None,
+            frame_end: ResourceState::UNDEFINED
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    pub fn add_pass(
+        &mut self,
+        name: &str,
+        setup: impl FnOnce(&mut PassBuilder),
+        execute: impl Fn(&PassContext) + 'static)
+    {
+        let mut pass = Pass {
+            name: name.to_owned(),
+            reads: vec![],
+            writes: vec![],
+            side_effects: false,
+            execute: Box::new(execute)
+        };
+        setup(&mut PassBuilder { pass: &mut pass });
+
+        self.passes.push(pass);
+    }
+
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.order.iter().map(|&i| self.passes[i].name.as_str()).collect()
+    }
+
+    pub fn compile(&mut self, device: &Arc<Device>) {
+        self.release();
+        self.device = Some(device.clone());
+
+        self.schedule();
+        self.allocate(device);
+    }
+
+    fn schedule(&mut self) {
+        let alive = self.cull();
+        self.order = self.sort(&alive);
+    }
+
+    //keeps only the passes contributing to imported outputs, passes can be declared in any order
+    //so it repeats until no more passes are found to be needed
+    fn cull(&self) -> Vec<bool> {
+        let mut needed_images: Vec<bool> = self.images.iter()
+            .map(|image| matches!(image.kind, ImageKind::Imported { .. }))
+            .collect();
+        let mut needed_buffers: Vec<bool> = self.buffers.iter()
+            .map(|buffer| matches!(buffer.kind, BufferKind::Imported { .. }))
+            .collect();
+
+        let mut alive = vec![false; self.passes.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for (i, pass) in self.passes.iter().enumerate() {
+                if alive[i] {
+                    continue;
+                }
+
+                let contributes = pass.writes.iter().any(|access| match *access {
+                    Access::Image(image, _) => needed_images[image],
+                    Access::Buffer(buffer, _) => needed_buffers[buffer],
+                });
+
+                if !(contributes || pass.side_effects) {
+                    continue;
+                }
+
+                alive[i] = true;
+                changed = true;
+                for access in pass.reads.iter().chain(&pass.writes) {
+                    match *access {
+                        Access::Image(image, _) => needed_images[image] = true,
+                        Access::Buffer(buffer, _) => needed_buffers[buffer] = true,
+                    }
+                }
+            }
+        }
+        alive
+    }
+
+    //orders the alive passes so every pass runs after the passes it depends on, ties keep declaration order
+    fn sort(&self, alive: &[bool]) -> Vec<usize> {
+        let passes: Vec<usize> = (0..self.passes.len()).filter(|&i| alive[i]).collect();
+
+        let mut dependencies = vec![vec![]; self.passes.len()];
+        for &pass in &passes {
+            for &other in &passes {
+                if pass != other && self.depends_on(pass, other, alive) {
+                    dependencies[pass].push(other);
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(passes.len());
+        let mut scheduled = vec![false; self.passes.len()];
+        while order.len() < passes.len() {
+            let next = passes.iter()
+                .copied()
+                .find(|&i| !scheduled[i] && dependencies[i].iter().all(|&d| scheduled[d]))
+                .expect("render graph has a cycle");
+
+            scheduled[next] = true;
+            order.push(next);
+        }
+        order
+    }
+
+    //reads run after the write they see, writes to the same resource run in declaration order
+    fn depends_on(&self, pass: usize, other: usize, alive: &[bool]) -> bool {
+        let accesses = self.passes[pass].reads.iter().chain(&self.passes[pass].writes);
+
+        accesses.map(Access::resource).any(|resource| {
+            let (pass_writes, other_writes) = (self.passes[pass].writes(resource), self.passes[other].writes(resource));
+
+            match (pass_writes, other_writes) {
+                (true, true) => other < pass,
+                //read after write
+                (false, true) => self.producer(pass, resource, alive) == Some(other),
+                //write after read, once the reader's write is overwritten
+                (true, false) => self.passes[other].reads(resource)
+                    && self.producer(other, resource, alive).is_some_and(|producer| producer < pass),
+                (false, false) => false,
+            }
+        })
+    }
+
+    //the write a read sees: the last one declared before the reader, or the first one when it's declared after
+    fn producer(&self, reader: usize, resource: Resource, alive: &[bool]) -> Option<usize> {
+        let writers: Vec<usize> = (0..self.passes.len())
+            .filter(|&i| alive[i] && i != reader && self.passes[i].writes(resource))
+            .collect();
+
+        writers.iter().rev().find(|&&writer| writer < reader).or(writers.first()).copied()
+    }
+
+    fn lifetime(&self, image: usize) -> Option<(usize, usize)> {
+        let uses: Vec<usize> = self.order.iter()
+            .enumerate()
+            .filter(|(_, &pass)| self.passes[pass].reads.iter()
+                .chain(&self.passes[pass].writes)
+                .any(|access| matches!(*access, Access::Image(i, _) if i == image)))
+            .map(|(n, _)| n)
+            .collect();
+
+        Some((*uses.first()?, *uses.last()?))
+    }
+
+    fn image_usage(&self, image: usize) -> vk::ImageUsageFlags {
+        self.passes.iter()
+            .flat_map(|pass| pass.reads.iter().chain(&pass.writes))
+            .filter_map(|access| match *access {
+                Access::Image(i, state) if i == image => Some(usage_for(state.layout)),
+                _ => None,
+            })
+            .fold(vk::ImageUsageFlags::empty(), |usage, flags| usage | flags)
+    }
+
+    fn allocate(&mut self, device: &Arc<Device>) {
+        let mut images = vec![];
+        for i in 0..self.images.len() {
+            let desc = match self.images[i].kind {
+                ImageKind::Transient(desc) => desc,
+                ImageKind::Imported { .. } => continue,
+            };
+            let lifetime = match self.lifetime(i) {
+                Some(lifetime) => lifetime,
+                None => continue,
+            };
+
+            let image = Self::new_image(device, desc, self.image_usage(i));
+            let requirements = unsafe {
+                device.logical.get_image_memory_requirements(image)
+            };
+            self.images[i].image = image;
+
+            images.push((i, lifetime, requirements));
+        }
+
+        let slots = assign_slots(&images);
+
+        for (requirements, occupants) in &slots {
+            let allocation = device.allocator.lock().unwrap().allocate(&AllocationCreateDesc {
+                name: "render graph",
+                requirements: *requirements,
+                location: MemoryLocation::GpuOnly,
+                linear: false
+            }).unwrap();
+
+            for (n, &i) in occupants.iter().enumerate() {
+                let image = &mut self.images[i];
+                let format = match image.kind {
+                    ImageKind::Transient(desc) => desc.format,
+                    ImageKind::Imported { .. } => unreachable!(),
+                };
+
+                unsafe {
+                    device.logical.bind_image_memory(image.image, allocation.memory(), allocation.offset()).unwrap();
+                }
+                image.view = Self::new_view(device, image.image, format, image.range);
+                image.aliases = n.checked_sub(1).map(|previous| occupants[previous]);
+
+                device.set_object_name(image.image, &image.name);
+                device.set_object_name(image.view, &format!("{} view", image.name));
+            }
+
+            self.memory.push(allocation);
+        }
+
+        for buffer in &mut self.buffers {
+            if let BufferKind::Transient(desc) = buffer.kind {
+                let owned = Buffer::new(device, desc.size, desc.usage, MemoryLocation::GpuOnly);
+                owned.set_name(&buffer.name);
+                buffer.buffer = owned.buffer;
+                buffer.owned = Some(owned);
+            }
+        }
+
+        self.track_frame_ends(&slots);
+    }
+
+    //transients are shared by the frames in flight, so the first uses of a frame have to wait on the
+    //last uses of the frame before, found by running through a frame's accesses once
+    fn track_frame_ends(&mut self, slots: &[(vk::MemoryRequirements, Vec<usize>)]) {
+        let mut tracker = ResourceTracker::new();
+        for image in &self.images {
+            if let ImageKind::Transient(_) = image.kind {
+                tracker.register_image(image.image, image.range, ResourceState::UNDEFINED);
+            }
+        }
+
+        for &pass_index in &self.order {
+            let pass = &self.passes[pass_index];
+
+            for access in pass.reads.iter().chain(&pass.writes) {
+                match *access {
+                    Access::Image(i, state) if matches!(self.images[i].kind, ImageKind::Transient(_)) => {
+                        tracker.use_image(self.images[i].image, state);
+                    },
+                    Access::Buffer(i, state) if matches!(self.buffers[i].kind, BufferKind::Transient(_)) => {
+                        tracker.use_buffer(self.buffers[i].buffer, state);
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        //aliased images wait on every use of their memory
+        for (_, occupants) in slots {
+            let frame_end = occupants.iter()
+                .filter_map(|&i| tracker.image_state(self.images[i].image))
+                .fold(ResourceState::UNDEFINED, |end, state| ResourceState::new(
+                    end.stage | state.stage,
+                    end.access | state.access,
+                    vk::ImageLayout::UNDEFINED));
+
+            for &i in occupants {
+                self.images[i].frame_end = frame_end;
+            }
+        }
+
+        for buffer in &mut self.buffers {
+            if let BufferKind::Transient(_) = buffer.kind {
+                buffer.frame_end = tracker.buffer_state(buffer.buffer).unwrap_or(ResourceState::UNDEFINED);
+            }
+        }
+    }
+
+    fn new_image(device: &Device, desc: ImageDesc, usage: vk::ImageUsageFlags) -> vk::Image {
+        let info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(desc.format)
+            .extent(vk::Extent3D {
+                width: desc.extent.width,
+                height: desc.extent.height,
+                depth: 1
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        unsafe {
+            device.logical.create_image(&info, None).unwrap()
+        }
+    }
+
+    fn new_view(device: &Device, image: vk::Image, format: vk::Format, range: vk::ImageSubresourceRange) -> vk::ImageView {
+        let info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(range);
+
+        unsafe {
+            device.logical.create_image_view(&info, None).unwrap()
+        }
+    }
+
+    //with a profiler every pass is timed in a scope of its name, passes can hand the command buffer to the app
+    pub fn execute(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        mut profiler: Option<&mut GpuProfiler>,
+        app: Option<&dyn Fn(&PassContext)>)
+    {
+        let device = self.device.as_deref().expect("render graph is not compiled");
+
+        for image in &self.images {
+            match image.kind {
+                ImageKind::Imported { initial, .. } => self.tracker.register_image(image.image, image.range, initial),
+                ImageKind::Transient(_) if image.image != vk::Image::null() => {
+                    self.tracker.register_image(image.image, image.range, image.frame_end)
+                },
+                ImageKind::Transient(_) => {},
+            }
+        }
+        for buffer in &self.buffers {
+            if let BufferKind::Transient(_) = buffer.kind {
+                self.tracker.register_buffer(buffer.buffer, buffer.frame_end);
+            }
+        }
+
+        let mut used = vec![false; self.images.len()];
+        for &pass_index in &self.order {
+            let pass = &self.passes[pass_index];
+
+            for access in pass.reads.iter().chain(&pass.writes) {
+                match *access {
+                    Access::Image(i, state) => {
+                        //aliased memory is handed over once its previous occupant is done
+                        if let Some(previous) = self.images[i].aliases.filter(|_| !used[i]) {
+                            let previous = self.tracker.image_state(self.images[previous].image).unwrap();
+                            let current = self.images[i].frame_end;
+
+                            self.tracker.register_image(self.images[i].image, self.images[i].range, ResourceState::new(
+                                previous.stage | current.stage,
+                                previous.access | current.access,
+                                vk::ImageLayout::UNDEFINED));
+                        }
+                        used[i] = true;
+
+                        self.tracker.use_image(self.images[i].image, state);
+                    },
+                    Access::Buffer(i, state) => self.tracker.use_buffer(self.buffers[i].buffer, state),
+                }
+            }
+            self.tracker.flush(device, command_buffer);
+
+            device.begin_label(command_buffer, &pass.name);
+            if let Some(profiler) = profiler.as_mut() {
+                profiler.begin_scope(command_buffer, frame_index, &pass.name);
+            }
+
+            (pass.execute)(&PassContext {
+                device,
+                command_buffer,
+                frame_index,
+                images: &self.images,
+                buffers: &self.buffers,
+                app
+            });
+
+            if let Some(profiler) = profiler.as_mut() {
+                profiler.end_scope(command_buffer, frame_index);
+            }
+            device.end_label(command_buffer);
+        }
+
+        for image in &self.images {
+            if let ImageKind::Imported { final_state: Some(state), .. } = image.kind {
+                self.tracker.use_image(image.image, state);
+            }
+        }
+        for buffer in &self.buffers {
+            if let BufferKind::Imported { final_state: Some(state) } = buffer.kind {
+                self.tracker.use_buffer(buffer.buffer, state);
+            }
+        }
+        self.tracker.flush(device, command_buffer);
+    }
+
+    //the device has to be done with the transient resources
+    fn release(&mut self) {
+        let device = match self.device.take() {
+            Some(device) => device,
+            None => return,
+        };
+
+        for image in &mut self.images {
+            if let ImageKind::Transient(_) = image.kind {
+                if image.image != vk::Image::null() {
+                    unsafe {
+                        device.logical.destroy_image_view(image.view, None);
+                        device.logical.destroy_image(image.image, None);
+                    }
+                }
+                image.image = vk::Image::null();
+                image.view = vk::ImageView::null();
+                image.aliases = None;
+                image.frame_end = ResourceState::UNDEFINED;
+            }
+        }
+
+        for buffer in &mut self.buffers {
+            if buffer.owned.take().is_some() {
+                buffer.buffer = vk::Buffer::null();
+                buffer.frame_end = ResourceState::UNDEFINED;
+            }
+        }
+
+        for allocation in self.memory.drain(..) {
+            device.allocator.lock().unwrap().free(allocation).unwrap();
+        }
+
+        self.tracker = ResourceTracker::new();
+    }
+}
+
+impl Drop for RenderGraph {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+fn usage_for(layout: vk::ImageLayout) -> vk::ImageUsageFlags {
+    match layout {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::ImageUsageFlags::SAMPLED,
+        vk::ImageLayout::GENERAL => vk::ImageUsageFlags::STORAGE,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::ImageUsageFlags::TRANSFER_SRC,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::ImageUsageFlags::TRANSFER_DST,
+        _ => vk::ImageUsageFlags::empty(),
+    }
+}
+
+//packs images with disjoint lifetimes, given as the first and last position in the pass order, into shared
+//memory, returns the slots with the images living in them in order of use
+fn assign_slots(images: &[(usize, (usize, usize), vk::MemoryRequirements)]) -> Vec<(vk::MemoryRequirements, Vec<usize>)> {
+    //memory slots with the pass index their last occupant is done at
+    let mut slots: Vec<(vk::MemoryRequirements, usize, Vec<usize>)> = vec![];
+
+    for &(i, (first, last), requirements) in images {
+        let free_slot = slots.iter_mut().find(|(slot, end, _)| {
+            *end < first && slot.memory_type_bits & requirements.memory_type_bits != 0
+        });
+
+        match free_slot {
+            Some((slot, end, occupants)) => {
+                slot.size = slot.size.max(requirements.size);
+                slot.alignment = slot.alignment.max(requirements.alignment);
+                slot.memory_type_bits &= requirements.memory_type_bits;
+                *end = last;
+                occupants.push(i);
+            },
+            None => slots.push((requirements, last, vec![i])),
+        }
+    }
+
+    slots.into_iter().map(|(requirements, _, occupants)| (requirements, occupants)).collect()
+}
+
+fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM
+        | vk::Format::X8_D24_UNORM_PACK32
+        | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+fn subresource_range(aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sync;
+
+    fn color(graph: &mut RenderGraph, name: &str) -> ImageHandle {
+        graph.create_image(name, ImageDesc {
+            format: vk::Format::R8G8B8A8_UNORM,
+            extent: vk::Extent2D { width: 1, height: 1 }
+        })
+    }
+
+    fn import_output(graph: &mut RenderGraph) -> ImageHandle {
+        graph.import_image("output", sync::color_range(1), ResourceState::UNDEFINED, Some(ResourceState::PRESENT))
+    }
+
+    fn requirements(size: vk::DeviceSize) -> vk::MemoryRequirements {
+        vk::MemoryRequirements {
+            size,
+            alignment: 256,
+            memory_type_bits: 1
+        }
+    }
+
+    #[test]
+    fn passes_run_after_the_passes_they_read_from() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let lighting = color(&mut graph, "lighting");
+        let gbuffer = color(&mut graph, "gbuffer");
+
+        graph.add_pass("composite", |pass| {
+            pass.read_image(lighting, ResourceState::FRAGMENT_READ)
+                .write_image(output, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("lighting", |pass| {
+            pass.read_image(gbuffer, ResourceState::FRAGMENT_READ)
+                .write_image(lighting, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("gbuffer", |pass| {
+            pass.write_image(gbuffer, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.schedule();
+
+        assert_eq!(graph.pass_names(), ["gbuffer", "lighting", "composite"]);
+    }
+
+    #[test]
+    fn writes_keep_declaration_order_around_reads() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let history = color(&mut graph, "history");
+
+        graph.add_pass("write", |pass| {
+            pass.write_image(history, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("overwrite", |pass| {
+            pass.write_image(history, ResourceState::COLOR_ATTACHMENT)
+                .write_image(output, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("read", |pass| {
+            pass.read_image(history, ResourceState::FRAGMENT_READ)
+                .write_image(output, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.schedule();
+
+        assert_eq!(graph.pass_names(), ["write", "overwrite", "read"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn cycles_are_rejected() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let a = color(&mut graph, "a");
+        let b = color(&mut graph, "b");
+
+        graph.add_pass("a", |pass| {
+            pass.read_image(b, ResourceState::FRAGMENT_READ)
+                .write_image(a, ResourceState::COLOR_ATTACHMENT)
+                .write_image(output, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("b", |pass| {
+            pass.read_image(a, ResourceState::FRAGMENT_READ)
+                .write_image(b, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.schedule();
+    }
+
+    #[test]
+    fn passes_not_reaching_an_output_are_culled() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let used = color(&mut graph, "used");
+        let unused = color(&mut graph, "unused");
+
+        graph.add_pass("present", |pass| {
+            pass.read_image(used, ResourceState::FRAGMENT_READ)
+                .write_image(output, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("unused", |pass| {
+            pass.write_image(unused, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        //declared after its consumer
+        graph.add_pass("used", |pass| {
+            pass.write_image(used, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("capture", |pass| {
+            pass.read_image(unused, ResourceState::TRANSFER_SRC)
+                .side_effects();
+        }, |_| {});
+        graph.schedule();
+
+        assert_eq!(graph.pass_names(), ["unused", "used", "present", "capture"]);
+
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let unused = color(&mut graph, "unused");
+
+        graph.add_pass("unused", |pass| {
+            pass.write_image(unused, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("present", |pass| {
+            pass.write_image(output, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.schedule();
+
+        assert_eq!(graph.pass_names(), ["present"]);
+    }
+
+    #[test]
+    fn lifetimes_follow_the_pass_order() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let a = color(&mut graph, "a");
+        let b = color(&mut graph, "b");
+
+        graph.add_pass("b", |pass| {
+            pass.read_image(a, ResourceState::FRAGMENT_READ)
+                .write_image(b, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("a", |pass| {
+            pass.write_image(a, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.add_pass("present", |pass| {
+            pass.read_image(b, ResourceState::FRAGMENT_READ)
+                .write_image(output, ResourceState::COLOR_ATTACHMENT);
+        }, |_| {});
+        graph.schedule();
+
+        assert_eq!(graph.lifetime(a.0), Some((0, 1)));
+        assert_eq!(graph.lifetime(b.0), Some((1, 2)));
+        assert_eq!(graph.lifetime(output.0), Some((2, 2)));
+    }
+
+    #[test]
+    fn images_with_disjoint_lifetimes_share_memory() {
+        let slots = assign_slots(&[
+            (0, (0, 1), requirements(1024)),
+            (1, (1, 2), requirements(4096)),
+            (2, (2, 3), requirements(2048)),
+            (3, (3, 3), vk::MemoryRequirements { memory_type_bits: 2, ..requirements(512) })
+        ]);
+
+        assert_eq!(slots.len(), 3);
+        assert_eq!(slots[0].1, [0, 2]);
+        assert_eq!(slots[0].0.size, 2048);
+        assert_eq!(slots[1].1, [1]);
+        //incompatible memory types never alias
+        assert_eq!(slots[2].1, [3]);
+    }
+}
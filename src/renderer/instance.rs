@@ -0,0 +1,182 @@
+use super::debug::MessengerState;
+use super::config::RendererConfig;
+
+use ash::{vk, extensions::ext};
+use serde::{Deserialize, Serialize};
+use std::{ffi, sync::Arc};
+
+pub const VALIDATION_LAYER: &ffi::CStr = c"VK_LAYER_KHRONOS_validation";
+
+//VK_EXT_validation_features toggles
+#[derive(Clone, Copy, Default, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ValidationFeatures {
+    pub gpu_assisted: bool,
+    pub synchronization: bool,
+    pub best_practices: bool,
+    pub debug_printf: bool
+}
+
+impl ValidationFeatures {
+    //comma separated list of gpu, sync, best and printf
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let mut features = Self::default();
+
+        for feature in value.split(',').map(str::trim) {
+            match feature {
+                "gpu" => features.gpu_assisted = true,
+                "sync" => features.synchronization = true,
+                "best" => features.best_practices = true,
+                "printf" => features.debug_printf = true,
+                "" => {},
+                _ => anyhow::bail!("unknown validation feature {:?}", feature),
+            }
+        }
+
+        Ok(features)
+    }
+
+    pub fn enables(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+        let mut enables = vec![];
+
+        //gpu assisted validation and debug printf share the instrumentation and exclude each other
+        if self.gpu_assisted && self.debug_printf {
+            log::warn!("gpu assisted validation is disabled in favour of debug printf");
+        } else if self.gpu_assisted {
+            enables.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+            enables.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+        if self.debug_printf {
+            enables.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+        }
+        if self.synchronization {
+            enables.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION);
+        }
+        if self.best_practices {
+            enables.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+        }
+
+        enables
+    }
+}
+
+pub struct Instance {
+    pub entry: ash::Entry,
+    pub handle: ash::Instance,
+    //the callback chained into instance creation keeps reporting until destroy_instance returns
+    pub messenger_state: Option<Arc<MessengerState>>,
+    pub validation_features: ValidationFeatures
+}
+
+impl Instance {
+    //validation features only apply with the validation layer among the layers
+    pub fn new(
+        entry: ash::Entry,
+        config: &RendererConfig,
+        extension_names: &[*const i8],
+        layer_names: &[*const i8],
+        messenger_state: Option<Arc<MessengerState>>)
+    -> Self {
+        let app_name = ffi::CString::new(config.app_name.as_str()).unwrap();
+        let engine_name = ffi::CString::new(config.engine_name.as_str()).unwrap();
+        let validation_features = config.validation_features;
+
+        let app_info = vk::ApplicationInfo::builder()
+            .application_name(&app_name)
+            .engine_name(&engine_name)
+            .application_version(vk::make_api_version(0, 0, 0, 1))
+            .engine_version(vk::make_api_version(0, 0, 0, 1))
+            .api_version(config.vk_api_version());
+
+        let validation = layer_names.iter().any(|&name| unsafe { ffi::CStr::from_ptr(name) } == VALIDATION_LAYER);
+
+        let mut enables = if validation { validation_features.enables() } else { vec![] };
+        if !enables.is_empty() && !supports_layer_extension(&entry, VALIDATION_LAYER, vk::ExtValidationFeaturesFn::name()) {
+            log::warn!("validation features were requested but {:?} is not available", vk::ExtValidationFeaturesFn::name());
+            enables.clear();
+        }
+
+        let mut extension_names = extension_names.to_vec();
+        if !enables.is_empty() {
+            extension_names.push(vk::ExtValidationFeaturesFn::name().as_ptr());
+        }
+
+        let mut features_info = vk::ValidationFeaturesEXT::builder()
+            .enabled_validation_features(&enables);
+
+        let mut messenger_info = messenger_state.as_ref().map(|state| state.messenger_info());
+
+        let mut info = vk::InstanceCreateInfo::builder()
+            .application_info(&app_info)
+            .enabled_extension_names(&extension_names)
+            .enabled_layer_names(layer_names);
+        if let Some(messenger_info) = &mut messenger_info {
+            info = info.push_next(messenger_info);
+        }
+        if !enables.is_empty() {
+            info = info.push_next(&mut features_info);
+        }
+
+        let handle = unsafe {
+            entry.create_instance(&info, None).unwrap()
+        };
+
+        Self {
+            entry,
+            handle,
+            messenger_state,
+            validation_features
+        }
+    }
+}
+
+//everything created from the instance holds an Arc to it, so this runs last
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            self.handle.destroy_instance(None);
+        }
+    }
+}
+
+pub fn supports_layer(entry: &ash::Entry, name: &ffi::CStr) -> bool {
+    entry.enumerate_instance_layer_properties()
+        .unwrap_or_default()
+        .iter()
+        .any(|layer| unsafe { ffi::CStr::from_ptr(layer.layer_name.as_ptr()) } == name)
+}
+
+pub fn supports_extension(entry: &ash::Entry, name: &ffi::CStr) -> bool {
+    has_extension(entry, None, name)
+}
+
+//extensions provided by a layer are only listed when asking that layer
+pub fn supports_layer_extension(entry: &ash::Entry, layer: &ffi::CStr, name: &ffi::CStr) -> bool {
+    has_extension(entry, Some(layer), name)
+}
+
+fn has_extension(entry: &ash::Entry, layer: Option<&ffi::CStr>, name: &ffi::CStr) -> bool {
+    entry.enumerate_instance_extension_properties(layer)
+        .unwrap_or_default()
+        .iter()
+        .any(|extension| unsafe { ffi::CStr::from_ptr(extension.extension_name.as_ptr()) } == name)
+}
+
+//validation layer and debug utils, each only if present on this machine
+pub fn debug_support(entry: &ash::Entry, requested: bool) -> (bool, bool) {
+    if !requested {
+        return (false, false);
+    }
+
+    let validation = supports_layer(entry, VALIDATION_LAYER);
+    if !validation {
+        log::warn!("validation was requested but {:?} is not installed", VALIDATION_LAYER);
+    }
+
+    let debug_utils = supports_extension(entry, ext::DebugUtils::name());
+    if !debug_utils {
+        log::warn!("validation was requested but {:?} is not available", ext::DebugUtils::name());
+    }
+
+    (validation, debug_utils)
+}
@@ -0,0 +1,97 @@
+use super::texture::Texture;
+
+use glam::{Vec3, Vec4};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AlphaMode {
+    Opaque,
+    //discarded below the material's alpha cutoff
+    Mask,
+    Blend
+}
+
+//a texture and the uv set it's sampled with
+#[derive(Clone)]
+pub struct TextureRef {
+    pub texture: Arc<Texture>,
+    pub uv_set: u32
+}
+
+//metallic-roughness pbr, factors multiply the texture samples
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    pub base_color: Vec4,
+    //srgb
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic: f32,
+    pub roughness: f32,
+    //roughness in green, metallic in blue
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    //red channel
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    pub emissive: Vec3,
+    //srgb
+    pub emissive_texture: Option<TextureRef>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: "default".to_owned(),
+            base_color: Vec4::ONE,
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: Vec3::ZERO,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false
+        }
+    }
+}
+
+//std140 compatible, for uniform or storage buffers
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialParams {
+    pub base_color: [f32; 4],
+    //w holds the alpha cutoff, 0 unless masked
+    pub emissive: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32
+}
+
+impl Material {
+    pub fn params(&self) -> MaterialParams {
+        let alpha_cutoff = if self.alpha_mode == AlphaMode::Mask { self.alpha_cutoff } else { 0.0 };
+
+        MaterialParams {
+            base_color: self.base_color.to_array(),
+            emissive: self.emissive.extend(alpha_cutoff).to_array(),
+            metallic: self.metallic,
+            roughness: self.roughness,
+            normal_scale: self.normal_scale,
+            occlusion_strength: self.occlusion_strength
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
+}
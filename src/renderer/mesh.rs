@@ -0,0 +1,440 @@
+use super::{Buffer, Device};
+use super::material::{AlphaMode, Material};
+
+use ash::vk;
+use anyhow::{Result, bail};
+use glam::{Vec3, Vec4};
+use gpu_allocator::MemoryLocation;
+use std::{mem::size_of, path::Path, sync::Arc};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    //w is the bitangent sign
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+    pub color: [f32; 4]
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 3],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+            uv: [0.0; 2],
+            color: [1.0; 4]
+        }
+    }
+}
+
+impl Vertex {
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX
+        }
+    }
+
+    //locations 0 to 4 in field order
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
+        let attribute = |location, format, offset| vk::VertexInputAttributeDescription {
+            location,
+            binding: 0,
+            format,
+            offset: offset as u32
+        };
+
+        [
+            attribute(0, vk::Format::R32G32B32_SFLOAT, 0),
+            attribute(1, vk::Format::R32G32B32_SFLOAT, 12),
+            attribute(2, vk::Format::R32G32B32A32_SFLOAT, 24),
+            attribute(3, vk::Format::R32G32_SFLOAT, 40),
+            attribute(4, vk::Format::R32G32B32A32_SFLOAT, 48),
+        ]
+    }
+}
+
+//a range of the index buffer drawn with one material
+#[derive(Clone, Debug)]
+pub struct Submesh {
+    pub name: String,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub material: Option<usize>
+}
+
+#[derive(Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    //already offset to the submesh's vertices, so every submesh is drawn with a vertex offset of 0
+    pub indices: Vec<u32>,
+    pub submeshes: Vec<Submesh>,
+    //the mtl materials of an obj file, gltf materials are imported with the scene
+    pub materials: Vec<Material>
+}
+
+//what to do about a submesh's normals
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Normals {
+    Keep,
+    Smooth,
+    //vertices are unwelded so every triangle gets its own normal
+    Flat
+}
+
+impl MeshData {
+    //gltf files are flattened into one mesh, node transforms are ignored
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("obj") => Self::from_obj(path),
+            Some("gltf" | "glb") => {
+                let (document, buffers, _) = gltf::import(path)?;
+
+                let mut data = Self::default();
+                for mesh in document.meshes() {
+                    data.append(Self::from_gltf_mesh(&mesh, &buffers)?);
+                }
+                Ok(data)
+            },
+            _ => bail!("unknown mesh format {:?}", path),
+        }
+    }
+
+    pub fn from_obj(path: impl AsRef<Path>) -> Result<Self> {
+        let options = tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        };
+        let (models, materials) = tobj::load_obj(path.as_ref(), &options)?;
+
+        let mut data = Self::default();
+        match materials {
+            Ok(materials) => data.materials = materials.iter().map(obj_material).collect(),
+            Err(error) => log::warn!("failed to load the materials of {:?}: {}", path.as_ref(), error),
+        }
+
+        for model in models {
+            let mesh = &model.mesh;
+
+            let vertices = (0..mesh.positions.len() / 3).map(|i| {
+                let mut vertex = Vertex {
+                    position: [mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2]],
+                    ..Default::default()
+                };
+                if !mesh.normals.is_empty() {
+                    vertex.normal = [mesh.normals[3 * i], mesh.normals[3 * i + 1], mesh.normals[3 * i + 2]];
+                }
+                //obj puts the uv origin at the bottom left
+                if !mesh.texcoords.is_empty() {
+                    vertex.uv = [mesh.texcoords[2 * i], 1.0 - mesh.texcoords[2 * i + 1]];
+                }
+                if !mesh.vertex_color.is_empty() {
+                    vertex.color = [mesh.vertex_color[3 * i], mesh.vertex_color[3 * i + 1], mesh.vertex_color[3 * i + 2], 1.0];
+                }
+                vertex
+            }).collect();
+
+            let normals = if mesh.normals.is_empty() { Normals::Smooth } else { Normals::Keep };
+            let material = mesh.material_id.filter(|&id| id < data.materials.len());
+            data.push_submesh(
+                model.name,
+                vertices,
+                mesh.indices.clone(),
+                normals,
+                !mesh.texcoords.is_empty(),
+                material)?;
+        }
+
+        Ok(data)
+    }
+
+    //one submesh per triangle primitive, the material is the index into the document's materials
+    pub fn from_gltf_mesh(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data]) -> Result<Self> {
+        let mut data = Self::default();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!("skipping {:?} primitive of mesh {:?}", primitive.mode(), mesh.name());
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let Some(positions) = reader.read_positions() else {
+                bail!("mesh {:?} has a primitive without positions", mesh.name());
+            };
+            let mut vertices: Vec<Vertex> = positions
+                .map(|position| Vertex { position, ..Default::default() })
+                .collect();
+
+            let has_normals = reader.read_normals()
+                .map(|normals| vertices.iter_mut().zip(normals).for_each(|(vertex, normal)| vertex.normal = normal))
+                .is_some();
+            let has_tangents = reader.read_tangents()
+                .map(|tangents| vertices.iter_mut().zip(tangents).for_each(|(vertex, tangent)| vertex.tangent = tangent))
+                .is_some();
+            let has_uvs = reader.read_tex_coords(0)
+                .map(|uvs| vertices.iter_mut().zip(uvs.into_f32()).for_each(|(vertex, uv)| vertex.uv = uv))
+                .is_some();
+            if let Some(colors) = reader.read_colors(0) {
+                vertices.iter_mut().zip(colors.into_rgba_f32()).for_each(|(vertex, color)| vertex.color = color);
+            }
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+
+            //gltf wants flat normals when a primitive has none
+            let normals = if has_normals { Normals::Keep } else { Normals::Flat };
+            let name = format!("{} {}", mesh.name().unwrap_or("mesh"), primitive.index());
+            data.push_submesh(name, vertices, indices, normals, has_uvs && !has_tangents, primitive.material().index())?;
+        }
+
+        Ok(data)
+    }
+
+    fn push_submesh(
+        &mut self,
+        name: String,
+        mut vertices: Vec<Vertex>,
+        mut indices: Vec<u32>,
+        normals: Normals,
+        generate_tangents: bool,
+        material: Option<usize>) -> Result<()>
+    {
+        if let Some(&index) = indices.iter().find(|&&index| index as usize >= vertices.len()) {
+            bail!("submesh {:?} indexes vertex {} of {}", name, index, vertices.len());
+        }
+
+        if normals == Normals::Flat {
+            vertices = indices.iter().map(|&index| vertices[index as usize]).collect();
+            indices = (0..vertices.len() as u32).collect();
+        }
+        if normals != Normals::Keep {
+            compute_normals(&mut vertices, &indices);
+        }
+        if generate_tangents {
+            compute_tangents(&mut vertices, &indices);
+        }
+
+        let base_vertex = self.vertices.len() as u32;
+        self.submeshes.push(Submesh {
+            name,
+            first_index: self.indices.len() as u32,
+            index_count: indices.len() as u32,
+            material
+        });
+        self.vertices.extend(vertices);
+        self.indices.extend(indices.iter().map(|index| index + base_vertex));
+
+        Ok(())
+    }
+
+    pub fn append(&mut self, other: Self) {
+        let base_vertex = self.vertices.len() as u32;
+        let base_index = self.indices.len() as u32;
+        let base_material = self.materials.len();
+
+        self.vertices.extend(other.vertices);
+        self.indices.extend(other.indices.iter().map(|index| index + base_vertex));
+        self.submeshes.extend(other.submeshes.into_iter().map(|submesh| Submesh {
+            first_index: submesh.first_index + base_index,
+            material: submesh.material.map(|material| material + base_material),
+            ..submesh
+        }));
+        self.materials.extend(other.materials);
+    }
+}
+
+//the factors of an mtl material, its texture maps aren't loaded
+fn obj_material(material: &tobj::Material) -> Material {
+    let diffuse = material.diffuse.unwrap_or([1.0; 3]);
+    let alpha = material.dissolve.unwrap_or(1.0);
+
+    Material {
+        name: material.name.clone(),
+        base_color: Vec4::new(diffuse[0], diffuse[1], diffuse[2], alpha),
+        metallic: 0.0,
+        //from the blinn-phong exponent
+        roughness: material.shininess.map_or(1.0, |shininess| (2.0 / (shininess.max(0.0) + 2.0)).sqrt()),
+        emissive: material.emissive.map_or(Vec3::ZERO, Vec3::from),
+        alpha_mode: if alpha < 1.0 { AlphaMode::Blend } else { AlphaMode::Opaque },
+        ..Default::default()
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(a: [f32; 3], fallback: [f32; 3]) -> [f32; 3] {
+    let length = dot(a, a).sqrt();
+    if length > f32::EPSILON { [a[0] / length, a[1] / length, a[2] / length] } else { fallback }
+}
+
+fn triangles(indices: &[u32]) -> impl Iterator<Item = [usize; 3]> + '_ {
+    indices.chunks_exact(3).map(|triangle| [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize])
+}
+
+//smooth normals, each face weighted by its area
+fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![[0.0f32; 3]; vertices.len()];
+
+    for [a, b, c] in triangles(indices) {
+        let face = cross(
+            sub(vertices[b].position, vertices[a].position),
+            sub(vertices[c].position, vertices[a].position));
+        for i in [a, b, c] {
+            normals[i] = [normals[i][0] + face[0], normals[i][1] + face[1], normals[i][2] + face[2]];
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = normalize(normal, [0.0, 0.0, 1.0]);
+    }
+}
+
+//per vertex tangents from the uv gradients, orthogonalized against the normal
+fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![[0.0f32; 3]; vertices.len()];
+    let mut bitangents = vec![[0.0f32; 3]; vertices.len()];
+
+    for [a, b, c] in triangles(indices) {
+        let edge1 = sub(vertices[b].position, vertices[a].position);
+        let edge2 = sub(vertices[c].position, vertices[a].position);
+        let du1 = vertices[b].uv[0] - vertices[a].uv[0];
+        let dv1 = vertices[b].uv[1] - vertices[a].uv[1];
+        let du2 = vertices[c].uv[0] - vertices[a].uv[0];
+        let dv2 = vertices[c].uv[1] - vertices[a].uv[1];
+
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() <= f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
+
+        let tangent = [0, 1, 2].map(|i| (edge1[i] * dv2 - edge2[i] * dv1) * r);
+        let bitangent = [0, 1, 2].map(|i| (edge2[i] * du1 - edge1[i] * du2) * r);
+        for i in [a, b, c] {
+            tangents[i] = [0, 1, 2].map(|j| tangents[i][j] + tangent[j]);
+            bitangents[i] = [0, 1, 2].map(|j| bitangents[i][j] + bitangent[j]);
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let n = vertex.normal;
+        let t = tangents[i];
+        let t = normalize(sub(t, n.map(|x| x * dot(n, t))), [1.0, 0.0, 0.0]);
+        let w = if dot(cross(n, t), bitangents[i]) < 0.0 { -1.0 } else { 1.0 };
+
+        vertex.tangent = [t[0], t[1], t[2], w];
+    }
+}
+
+pub struct Mesh {
+    pub device: Arc<Device>,
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub vertex_count: u32,
+    pub index_count: u32,
+    pub submeshes: Vec<Submesh>
+}
+
+impl Mesh {
+    pub fn load(device: &Arc<Device>, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let mesh = Self::new(device, &MeshData::load(path)?)?;
+        mesh.set_name(&path.to_string_lossy());
+
+        Ok(mesh)
+    }
+
+    pub fn new(device: &Arc<Device>, data: &MeshData) -> Result<Self> {
+        if data.indices.is_empty() {
+            bail!("mesh has no triangles");
+        }
+
+        let vertex_buffer = Self::upload(device, as_bytes(&data.vertices), vk::BufferUsageFlags::VERTEX_BUFFER);
+        let index_buffer = Self::upload(device, as_bytes(&data.indices), vk::BufferUsageFlags::INDEX_BUFFER);
+
+        Ok(Self {
+            device: device.clone(),
+            vertex_buffer,
+            index_buffer,
+            vertex_count: data.vertices.len() as u32,
+            index_count: data.indices.len() as u32,
+            submeshes: data.submeshes.clone()
+        })
+    }
+
+    pub fn set_name(&self, name: &str) {
+        self.vertex_buffer.set_name(&format!("{} vertices", name));
+        self.index_buffer.set_name(&format!("{} indices", name));
+    }
+
+    fn upload(device: &Arc<Device>, data: &[u8], usage: vk::BufferUsageFlags) -> Buffer {
+        let staging = Buffer::new_staging(device, data);
+        let buffer = Buffer::new(
+            device,
+            data.len() as vk::DeviceSize,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly);
+
+        //submit_once waits for the copy, so the staging buffer can go right after
+        device.submit_once(|command_buffer| unsafe {
+            let region = vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size: data.len() as vk::DeviceSize
+            };
+            device.logical.cmd_copy_buffer(command_buffer, staging.buffer, buffer.buffer, &[region]);
+        });
+
+        buffer
+    }
+
+    pub fn bind(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.device.logical.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.buffer], &[0]);
+            self.device.logical.cmd_bind_index_buffer(command_buffer, self.index_buffer.buffer, 0, vk::IndexType::UINT32);
+        }
+    }
+
+    //bind first
+    pub fn draw_submesh(&self, command_buffer: vk::CommandBuffer, submesh: usize) {
+        let submesh = &self.submeshes[submesh];
+        unsafe {
+            self.device.logical.cmd_draw_indexed(command_buffer, submesh.index_count, 1, submesh.first_index, 0, 0);
+        }
+    }
+
+    pub fn draw(&self, command_buffer: vk::CommandBuffer) {
+        self.bind(command_buffer);
+        unsafe {
+            self.device.logical.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+        }
+    }
+}
+
+pub fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+    }
+}
@@ -1,237 +1,296 @@
+pub mod config;
+pub mod instance;
 pub mod debug;
 pub mod device;
 pub mod window;
 pub mod swapchain;
 pub mod pipeline;
 pub mod shader;
-
-use debug::Debug;
-use device::Device;
+pub mod buffer;
+pub mod texture;
+pub mod mesh;
+pub mod material;
+pub mod scene;
+pub mod camera;
+pub mod sync;
+pub mod graph;
+pub mod timeline;
+pub mod deletion;
+pub mod profiler;
+pub mod stats;
+pub mod viewport;
+pub mod scene_renderer;
+
+use config::{RendererConfig, WindowConfig};
+use instance::Instance;
+use debug::{Debug, MessageFilter, MessengerState};
+use device::{Device, CommandPool};
 use window::Window;
-use swapchain::Swapchain;
-use pipeline::Pipeline;
+use pipeline::{Pipeline, MeshPipeline, RenderPass};
 use shader::Shader;
+use buffer::Buffer;
+use deletion::{DeletionQueue, Retired};
+use viewport::Viewport;
 
 use ash::{vk, extensions::*};
-use std::{ffi};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+use winit::{event_loop::{EventLoop, EventLoopWindowTarget}, window::WindowId};
+
+//an acquired swapchain image of a window and the frame slot rendering to it
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub window: WindowId,
+    pub image_index: usize,
+    pub slot: usize,
+    pub command_buffer: vk::CommandBuffer
+}
 
+//fields hold Arcs to what they were created from, so they can drop in any order
 pub struct Renderer {
-    pub instance: ash::Instance,
-    pub debug: Debug,
-    pub device: Device,
-    pub window: Window,
-    pub render_pass: vk::RenderPass,
-    pub swapchain: Swapchain,
+    pub config: RendererConfig,
+    pub instance: Arc<Instance>,
+    pub debug: Option<Arc<Debug>>,
+    pub device: Arc<Device>,
+    pub event_loop: Option<EventLoop<()>>,
+    //shared by every viewport, which all use the primary window's surface format
+    pub render_pass: Option<RenderPass>,
     pub pipeline: Pipeline,
-    pub command_pool: vk::CommandPool,
-    pub command_buffers: Vec<vk::CommandBuffer>
+    pub mesh_pipeline: Arc<MeshPipeline>,
+    pub command_pool: CommandPool,
+    pub viewports: HashMap<WindowId, Viewport>,
+    //closing it ends the application
+    pub primary: WindowId,
+    //shared with the scene renderers, which retire their resources into it when dropped
+    pub deletion_queue: Rc<RefCell<DeletionQueue>>
 }
 
 impl Renderer {
-    pub fn new() -> Self {
+    //fails for invalid configs, like ones asking for vulkan older than 1.3
+    pub fn new(config: RendererConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+
         let entry = ash::Entry::linked();
-        let (event_loop, window_handle) = Window::new_handle();
+        let event_loop = EventLoop::new();
+        let window_handle = Window::new_handle(&event_loop, &config.window);
+
+        let (validation, debug_utils) = instance::debug_support(&entry, config.validation);
 
-        let mut extension_names = vec![
-            ext::DebugUtils::name().as_ptr(),
-            khr::Surface::name().as_ptr()];
+        let mut extension_names = vec![khr::Surface::name().as_ptr()];
         for window_extension in ash_window::enumerate_required_extensions(&window_handle).unwrap() {
             extension_names.push(window_extension.as_ptr());
         }
-        
-        let layer_names = vec!["VK_LAYER_KHRONOS_validation\0".as_ptr() as *const i8];
-
-        let instance = Self::new_instance(&entry, &extension_names, &layer_names);
-
-        let debug = Debug::new(&entry, &instance);
-
-        let device = Device::new(&instance, &layer_names);
-
-        let window = Window::new(event_loop, window_handle, device.physical, &entry, &instance);
+        if debug_utils {
+            extension_names.push(ext::DebugUtils::name().as_ptr());
+        }
 
-        let render_pass = Self::new_render_pass(&device, &window);
+        let mut layer_names = vec![];
+        if validation {
+            layer_names.push(instance::VALIDATION_LAYER.as_ptr());
+        }
 
-        let swapchain = Swapchain::new(&instance, &device, &window, render_pass);
+        //kept alive until the device is created
+        let extra_layers: Vec<std::ffi::CString> = config.layers.iter()
+            .map(|name| std::ffi::CString::new(name.as_str()).unwrap())
+            .filter(|name| {
+                let supported = instance::supports_layer(&entry, name);
+                if !supported {
+                    log::warn!("layer {:?} is not installed", name);
+                }
+                supported
+            })
+            .collect();
+        layer_names.extend(extra_layers.iter().map(|name| name.as_ptr()));
+
+        let messenger_state = debug_utils.then(|| Arc::new(MessengerState::new(MessageFilter::default(), config.error_action)));
+
+        let instance = Arc::new(Instance::new(
+            entry,
+            &config,
+            &extension_names,
+            &layer_names,
+            messenger_state.clone()));
+
+        let debug = messenger_state.map(|state| Arc::new(Debug::new(&instance, &state)));
+
+        let device = Arc::new(Device::new(&instance, debug.clone(), &layer_names));
+        let window = Window::new(window_handle, &config.window, device.physical, &instance, None).unwrap();
+
+        //render passes are only needed on devices without dynamic rendering
+        let render_pass = match device.dynamic_rendering {
+            Some(_) => None,
+            None => Some(RenderPass::new(&device, window.format.format, device.depth_format)),
+        };
 
-        let pipeline = Pipeline::new(&device, swapchain.extent, render_pass);
+        let render_pass_handle = render_pass.as_ref().map(|render_pass| render_pass.render_pass);
+        let pipeline = Pipeline::new(&device, render_pass_handle, window.format.format);
+        let mesh_pipeline = Arc::new(MeshPipeline::new(&device, render_pass_handle, window.format.format));
 
-        let command_pool = Self::new_command_pool(&device);
+        let command_pool = CommandPool::new(
+            &device,
+            device.graphics_family.index,
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            "frame command pool");
 
-        let command_buffers = Self::new_command_buffers(&device, &swapchain, &pipeline, command_pool, render_pass);
+        let deletion_queue = Rc::new(RefCell::new(DeletionQueue::new(&device)));
 
-        Self {
+        let primary = window.handle.id();
+        let viewport = Viewport::new(
+            &device,
+            window,
+            render_pass_handle,
+            &pipeline,
+            command_pool.pool,
+            &config.window.title,
+            &config);
+
+        Ok(Self {
+            config,
             instance,
             debug,
-            device, 
-            window,
+            device,
+            event_loop: Some(event_loop),
             render_pass,
-            swapchain, 
             pipeline,
+            mesh_pipeline,
             command_pool,
-            command_buffers
+            viewports: HashMap::from([(primary, viewport)]),
+            primary,
+            deletion_queue
+        })
+    }
+
+    pub fn event_loop(&mut self) -> anyhow::Result<EventLoop<()>> {
+        match self.event_loop.take() {
+            None => anyhow::bail!("EventLoop was acquired before"),
+            Some(el) => Ok(el)
         }
     }
 
-    fn new_instance(entry: &ash::Entry, extension_names: &Vec<*const i8>, layer_names: &Vec<*const i8>) -> ash::Instance {
-        let app_name = ffi::CString::new("Ash App").unwrap();
-        let engine_name = ffi::CString::new("Ash Engine").unwrap();
+    //windows can only be created on the event loop's thread, from the loop or before it runs,
+    //fails for windows the shared pipelines or the graphics queue can't be used with
+    pub fn add_window(&mut self, target: &EventLoopWindowTarget<()>, config: &WindowConfig) -> anyhow::Result<WindowId> {
+        let handle = Window::new_handle(target, config);
+        let window = Window::new(handle, config, self.device.physical, &self.instance, Some(self.primary_viewport().window.format))?;
 
-        let app_info = vk::ApplicationInfo::builder()
-            .application_name(&app_name)
-            .engine_name(&engine_name)
-            .application_version(vk::make_api_version(0, 0, 0, 1))
-            .engine_version(vk::make_api_version(0, 0, 0, 1))
-            .api_version(vk::API_VERSION_1_3);
+        if !window.supports_present(self.device.physical, self.device.graphics_family.index) {
+            anyhow::bail!("the graphics queue can't present to window {:?}", config.title);
+        }
 
-        let info = vk::InstanceCreateInfo::builder()
-            .application_info(&app_info)
-            .enabled_extension_names(extension_names)
-            .enabled_layer_names(layer_names);
+        let id = window.handle.id();
+        let viewport = Viewport::new(
+            &self.device,
+            window,
+            self.render_pass_handle(),
+            &self.pipeline,
+            self.command_pool.pool,
+            &config.title,
+            &self.config);
+        self.viewports.insert(id, viewport);
+
+        Ok(id)
+    }
 
-        unsafe {
-            entry.create_instance(&info, None).unwrap()
+    //the primary window stays until the renderer is dropped
+    pub fn remove_window(&mut self, id: WindowId) {
+        if id == self.primary {
+            log::warn!("the primary window can't be removed");
+            return;
         }
-    }
 
-    fn new_render_pass(device: &Device, window: &Window) -> vk::RenderPass {
-        let attachments = [
-            vk::AttachmentDescription::builder()
-                .format(window.format.format)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .build()
-        ];
-
-        let color_attachment_references = [vk::AttachmentReference {
-            attachment: 0,
-            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        }];
-
-        let subpasses = [
-            vk::SubpassDescription::builder()
-                .color_attachments(&color_attachment_references)
-                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                .build()
-        ];
-
-        let subpass_dependencies = [
-            vk::SubpassDependency::builder()
-                .src_subpass(vk::SUBPASS_EXTERNAL)
-                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-                .dst_subpass(0)
-                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
-                .build()
-        ];
-
-        let info = vk::RenderPassCreateInfo::builder()
-            .attachments(&attachments)
-            .subpasses(&subpasses)
-            .dependencies(&subpass_dependencies);
+        //the window closes once its last frame is done
+        if let Some(mut viewport) = self.viewports.remove(&id) {
+            let last_submitted = viewport.last_submitted();
+            let command_buffers = Retired::CommandBuffers(self.command_pool.pool, std::mem::take(&mut viewport.command_buffers));
 
-        unsafe {
-            device.logical.create_render_pass(&info, None).unwrap()
+            let mut deletion_queue = self.deletion_queue.borrow_mut();
+            deletion_queue.push_at(&self.device.graphics_timeline, last_submitted, command_buffers);
+            deletion_queue.push_at(&self.device.graphics_timeline, last_submitted, Retired::Viewport(Box::new(viewport)));
         }
     }
 
-    fn new_command_pool(device: &Device) -> vk::CommandPool {
-        let info = vk::CommandPoolCreateInfo::builder()
-            .queue_family_index(device.graphics_family.index)
-            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+    pub fn viewport(&self, id: WindowId) -> Option<&Viewport> {
+        self.viewports.get(&id)
+    }
 
-        unsafe {
-            device.logical.create_command_pool(&info, None).unwrap()
-        }
+    pub fn viewport_mut(&mut self, id: WindowId) -> Option<&mut Viewport> {
+        self.viewports.get_mut(&id)
     }
 
-    fn new_command_buffers(
-        device: &Device, 
-        swapchain: &Swapchain,
-        pipeline: &Pipeline,
-        pool: vk::CommandPool, 
-        render_pass: vk::RenderPass)
-    -> Vec<vk::CommandBuffer> {
-        let alloc_info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(pool)
-            .command_buffer_count(swapchain.image_count as u32);
-
-        let command_buffers = unsafe {
-            device.logical.allocate_command_buffers(&alloc_info).unwrap()
-        };
+    pub fn primary_viewport(&self) -> &Viewport {
+        &self.viewports[&self.primary]
+    }
 
-        for (i, &command_buffer) in command_buffers.iter().enumerate() {
-            let begin_info = vk::CommandBufferBeginInfo::builder();
-
-            unsafe {
-                device.logical.begin_command_buffer(command_buffer, &begin_info).unwrap();
-            }
-
-            let clear_values = [
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    }
-                },
-            ];
-
-            let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-                .render_pass(render_pass)
-                .framebuffer(swapchain.framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: swapchain.extent,
-                })
-                .clear_values(&clear_values);
-
-            unsafe {
-                device.logical.cmd_begin_render_pass(
-                    command_buffer,
-                    &render_pass_begin_info,
-                    vk::SubpassContents::INLINE);
-
-                device.logical.cmd_bind_pipeline(
-                    command_buffer, 
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline.graphics
-                );
-
-                device.logical.cmd_draw(command_buffer, 3, 1, 0, 0);
-
-                device.logical.cmd_end_render_pass(command_buffer);
-
-                device.logical.end_command_buffer(command_buffer).unwrap();
-            }
-        }
-        command_buffers
+    pub fn primary_viewport_mut(&mut self) -> &mut Viewport {
+        self.viewports.get_mut(&self.primary).unwrap()
     }
-}
 
-impl Drop for Renderer {
-    fn drop(&mut self) {
-        unsafe {
-            self.device.logical.device_wait_idle().unwrap();
+    pub fn render_pass_handle(&self) -> Option<vk::RenderPass> {
+        self.render_pass.as_ref().map(|render_pass| render_pass.render_pass)
+    }
 
+    pub fn recreate_swapchain(&mut self, id: WindowId) -> bool {
+        let render_pass = self.render_pass_handle();
+        match self.viewports.get_mut(&id) {
+            Some(viewport) => viewport.recreate_swapchain(
+                render_pass,
+                &self.pipeline,
+                self.command_pool.pool,
+                &mut self.deletion_queue.borrow_mut()),
+            None => false,
+        }
+    }
 
-            self.device.logical.destroy_command_pool(self.command_pool, None);
+    //none for unknown windows, while minimized or when the swapchain went out of date, end_frame has to follow otherwise
+    pub fn begin_frame(&mut self, id: WindowId) -> Option<Frame> {
+        // resized, minimized or switched to or from fullscreen:
+        let outdated = self.viewports.get(&id)?.swapchain_outdated;
+        if outdated && !self.recreate_swapchain(id) {
+            return None;
+        }
 
-            self.device.logical.destroy_render_pass(self.render_pass, None);
+        // destroying resources the gpu is done with:
+        self.deletion_queue.borrow_mut().collect();
 
-            self.pipeline.cleanup(&self.device.logical);
+        self.viewports.get_mut(&id)?.begin_frame()
+    }
 
-            self.swapchain.cleanup(&self.device.logical);
+    //records and submits the frame, app draws into the main pass
+    pub fn end_frame(&mut self, frame: Frame, app: &dyn Fn(&graph::PassContext)) {
+        if let Some(viewport) = self.viewports.get_mut(&frame.window) {
+            viewport.end_frame(frame, app);
+        }
 
-            self.window.cleanup();
+        // validation errors of the frame in panic mode:
+        if let Some(debug) = &self.debug {
+            debug.state.check();
+        }
+    }
 
-            self.device.cleanup();
+    pub fn toggle_fullscreen(&mut self, id: WindowId) {
+        if let Some(viewport) = self.viewports.get_mut(&id) {
+            viewport.toggle_fullscreen();
+        }
+    }
 
-            self.debug.cleanup();
+    //only the primary window's geometry is remembered
+    pub fn save_window_geometry(&self) {
+        if !self.config.window.remember_geometry {
+            return;
+        }
+        if let Err(error) = self.primary_viewport().window.geometry(&self.config.window).save() {
+            log::warn!("failed to save the window geometry: {}", error);
+        }
+    }
+}
 
-            self.instance.destroy_instance(None);
+//the fields destroy themselves, but only once the gpu is done with them
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.logical.device_wait_idle().unwrap();
         }
+
+        // retired command buffers go before their pool:
+        self.deletion_queue.borrow_mut().collect();
     }
 }
\ No newline at end of file
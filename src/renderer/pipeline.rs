@@ -1,98 +1,205 @@
 use super::Device;
 use super::Shader;
+use super::Buffer;
+use super::mesh::Vertex;
+use super::material::Material;
 
 use ash::vk;
+use std::sync::Arc;
+
+//only used on devices without dynamic rendering
+pub struct RenderPass {
+    pub device: Arc<Device>,
+    pub render_pass: vk::RenderPass
+}
+
+impl RenderPass {
+    pub fn new(device: &Arc<Device>, color_format: vk::Format, depth_format: vk::Format) -> Self {
+        let attachments = [
+            vk::AttachmentDescription::builder()
+                .format(color_format)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                //layout transitions are left to the render graph
+                .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+            vk::AttachmentDescription::builder()
+                .format(depth_format)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+                .final_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build()
+        ];
+
+        let color_attachment_references = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let depth_attachment_reference = vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+        };
+
+        let subpasses = [
+            vk::SubpassDescription::builder()
+                .color_attachments(&color_attachment_references)
+                .depth_stencil_attachment(&depth_attachment_reference)
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .build()
+        ];
+
+        let subpass_dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+                .dst_subpass(0)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .build()
+        ];
+
+        let info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+
+        let render_pass = unsafe {
+            device.logical.create_render_pass(&info, None).unwrap()
+        };
+        device.set_object_name(render_pass, "main render pass");
+
+        Self {
+            device: device.clone(),
+            render_pass
+        }
+    }
+}
+
+impl Drop for RenderPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.logical.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
 
 pub struct Pipeline {
+    pub device: Arc<Device>,
     pub graphics: vk::Pipeline,
     pub layout: vk::PipelineLayout
 }
 
+//what differs between the pipelines drawing into the main pass
+pub struct GraphicsDesc<'a> {
+    pub shader_stages: &'a [vk::PipelineShaderStageCreateInfo],
+    pub vertex_bindings: &'a [vk::VertexInputBindingDescription],
+    pub vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+    pub layout: vk::PipelineLayout,
+    pub cull_mode: vk::CullModeFlags,
+    //depth is reversed, nearer fragments pass with greater values
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub blend: bool
+}
+
 impl Pipeline {
-    pub fn new(device: &Device, extent: vk::Extent2D, render_pass: vk::RenderPass) -> Self {
+    pub fn new(device: &Arc<Device>, render_pass: Option<vk::RenderPass>, color_format: vk::Format) -> Self {
         //entry_name not shader creation local because p_name of shader modules hold reference
         let entry_name = std::ffi::CString::new("main").unwrap();
         
         let vert_shader = Shader::new(
-            &device.logical, 
+            device,
             vk_shader_macros::include_glsl!("./shaders/foo.vert"),
             vk::ShaderStageFlags::VERTEX, 
             &entry_name);
         
         let frag_shader = Shader::new(
-            &device.logical, 
+            device,
             vk_shader_macros::include_glsl!("./shaders/foo.frag"),
             vk::ShaderStageFlags::FRAGMENT, 
             &entry_name);
 
-        let (graphics, layout) = Self::new_graphics(
-            &device.logical,
-            render_pass, 
-            extent,
-            &[vert_shader.stage_info, frag_shader.stage_info]);
+        let layout_info = vk::PipelineLayoutCreateInfo::builder();
+        let layout = unsafe {
+            device.logical.create_pipeline_layout(&layout_info, None).unwrap()
+        };
 
-        unsafe {
-            vert_shader.cleanup(&device.logical);
-            frag_shader.cleanup(&device.logical);
-        }
+        let graphics = Self::new_graphics(device, render_pass, &[color_format], &GraphicsDesc {
+            shader_stages: &[vert_shader.stage_info, frag_shader.stage_info],
+            vertex_bindings: &[],
+            vertex_attributes: &[],
+            layout,
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_test: false,
+            depth_write: false,
+            blend: true
+        });
+
+        device.set_object_name(vert_shader.module, "foo.vert");
+        device.set_object_name(frag_shader.module, "foo.frag");
+        device.set_object_name(graphics, "main pipeline");
+        device.set_object_name(layout, "main pipeline layout");
 
         Self {
+            device: device.clone(),
             graphics,
             layout
         }
     }
 
+    //every pipeline of the main pass renders into a color attachment and the depth buffer
     pub fn new_graphics(
-        logical_device: &ash::Device,
-        render_pass: vk::RenderPass,
-        extent: vk::Extent2D,
-        shader_stages: &[vk::PipelineShaderStageCreateInfo])
-    -> (vk::Pipeline, vk::PipelineLayout) {
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        device: &Device,
+        render_pass: Option<vk::RenderPass>,
+        color_formats: &[vk::Format],
+        desc: &GraphicsDesc)
+    -> vk::Pipeline {
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(desc.vertex_bindings)
+            .vertex_attribute_descriptions(desc.vertex_attributes);
         
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
             .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
 
 
-        let viewports = [
-            vk::Viewport {
-                x: 0.0,
-                y: 0.0,
-                width: extent.width as f32,
-                height: extent.height as f32,
-                min_depth: 0.0,
-                max_depth: 0.0
-            }
-        ];
-
-        let scissors = [
-            vk::Rect2D {
-                offset: vk::Offset2D {
-                    x: 0,
-                    y: 0,
-                },
-                extent
-            }
-        ];
-        
+        //set while recording, so one pipeline serves swapchains of any size
         let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states);
 
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
             .line_width(1.0)
             .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .cull_mode(vk::CullModeFlags::NONE)
+            .cull_mode(desc.cull_mode)
             .polygon_mode(vk::PolygonMode::FILL);
 
         let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
             .rasterization_samples(vk::SampleCountFlags::TYPE_1);
 
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(desc.depth_test)
+            .depth_write_enable(desc.depth_write)
+            .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL);
+
         let color_blend_attachments = [
             vk::PipelineColorBlendAttachmentState::builder()
-                .blend_enable(true)
+                .blend_enable(desc.blend)
                 .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
                 .color_blend_op(vk::BlendOp::ADD)
                 .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
                 .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
@@ -104,32 +211,394 @@ impl Pipeline {
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .attachments(&color_blend_attachments);
 
-        let layout_info = vk::PipelineLayoutCreateInfo::builder();
-        let layout = unsafe {
-            logical_device.create_pipeline_layout(&layout_info, None).unwrap()
-        };
+        //without a render pass the attachment formats are declared up front for dynamic rendering
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+            .color_attachment_formats(color_formats)
+            .depth_attachment_format(device.depth_format);
 
-        let info = vk::GraphicsPipelineCreateInfo::builder()
-            .stages(shader_stages)
+        let mut info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(desc.shader_stages)
             .vertex_input_state(&vertex_input_info)
             .input_assembly_state(&input_assembly_info)
             .viewport_state(&viewport_info)
             .rasterization_state(&rasterizer_info)
             .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
             .color_blend_state(&color_blend_info)
-            .layout(layout)
-            .render_pass(render_pass)
-            .subpass(0);
+            .dynamic_state(&dynamic_info)
+            .layout(desc.layout);
+        info = match render_pass {
+            Some(render_pass) => info.render_pass(render_pass).subpass(0),
+            None => info.push_next(&mut rendering_info),
+        };
         
+        let pipelines = unsafe {
+            device.logical.create_graphics_pipelines(device.pipeline_cache, &[info.build()], None).unwrap()
+        };
+        pipelines[0]
+    }
+
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.logical.destroy_pipeline(self.graphics, None);
+            self.device.logical.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+//base color, metallic-roughness, normal, occlusion and emissive, after the material's parameters
+pub const MATERIAL_TEXTURES: usize = 5;
+
+//draws Vertex meshes, set 0 holds the camera, set 1 the material and the push constants the model matrix
+pub struct MeshPipeline {
+    pub device: Arc<Device>,
+    //indexed by blended, then double sided
+    pub pipelines: [[vk::Pipeline; 2]; 2],
+    pub layout: vk::PipelineLayout,
+    pub camera_layout: vk::DescriptorSetLayout,
+    pub material_layout: vk::DescriptorSetLayout
+}
+
+impl MeshPipeline {
+    pub fn new(device: &Arc<Device>, render_pass: Option<vk::RenderPass>, color_format: vk::Format) -> Self {
+        let entry_name = std::ffi::CString::new("main").unwrap();
+
+        let vert_shader = Shader::new(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/mesh.vert"),
+            vk::ShaderStageFlags::VERTEX,
+            &entry_name);
+
+        let frag_shader = Shader::new(
+            device,
+            vk_shader_macros::include_glsl!("./shaders/mesh.frag"),
+            vk::ShaderStageFlags::FRAGMENT,
+            &entry_name);
+
+        let camera_layout = Self::new_set_layout(device, &[
+            (vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        ]);
+
+        let mut material_bindings = vec![(vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT)];
+        material_bindings.extend([(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT); MATERIAL_TEXTURES]);
+        let material_layout = Self::new_set_layout(device, &material_bindings);
+
+        let set_layouts = [camera_layout, material_layout];
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: std::mem::size_of::<[[f32; 4]; 4]>() as u32
+            }
+        ];
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+
+        let layout = unsafe {
+            device.logical.create_pipeline_layout(&layout_info, None).unwrap()
+        };
+
+        let vertex_bindings = [Vertex::binding_description()];
+        let vertex_attributes = Vertex::attribute_descriptions();
+
+        //blended surfaces are tested against the opaque ones but don't hide each other
+        let pipelines = [false, true].map(|blend| [false, true].map(|double_sided| {
+            Pipeline::new_graphics(device, render_pass, &[color_format], &GraphicsDesc {
+                shader_stages: &[vert_shader.stage_info, frag_shader.stage_info],
+                vertex_bindings: &vertex_bindings,
+                vertex_attributes: &vertex_attributes,
+                layout,
+                cull_mode: if double_sided { vk::CullModeFlags::NONE } else { vk::CullModeFlags::BACK },
+                depth_test: true,
+                depth_write: !blend,
+                blend
+            })
+        }));
+
+        device.set_object_name(vert_shader.module, "mesh.vert");
+        device.set_object_name(frag_shader.module, "mesh.frag");
+        device.set_object_name(layout, "mesh pipeline layout");
+        device.set_object_name(camera_layout, "camera set layout");
+        device.set_object_name(material_layout, "material set layout");
+        for (blend, pipelines) in pipelines.iter().enumerate() {
+            for (double_sided, &pipeline) in pipelines.iter().enumerate() {
+                device.set_object_name(pipeline, &format!("mesh pipeline blend {} double sided {}", blend, double_sided));
+            }
+        }
+
+        Self {
+            device: device.clone(),
+            pipelines,
+            layout,
+            camera_layout,
+            material_layout
+        }
+    }
+
+    pub fn pipeline(&self, material: &Material) -> vk::Pipeline {
+        self.pipelines[material.is_transparent() as usize][material.double_sided as usize]
+    }
+
+    //bindings are numbered in order
+    fn new_set_layout(device: &Device, bindings: &[(vk::DescriptorType, vk::ShaderStageFlags)]) -> vk::DescriptorSetLayout {
+        let set_layout_bindings: Vec<_> = bindings.iter()
+            .enumerate()
+            .map(|(i, &(ty, stages))| vk::DescriptorSetLayoutBinding::builder()
+                .binding(i as u32)
+                .descriptor_type(ty)
+                .descriptor_count(1)
+                .stage_flags(stages)
+                .build())
+            .collect();
+
+        let info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&set_layout_bindings);
+
+        unsafe {
+            device.logical.create_descriptor_set_layout(&info, None).unwrap()
+        }
+    }
+}
+
+impl Drop for MeshPipeline {
+    fn drop(&mut self) {
+        let logical = &self.device.logical;
+
+        unsafe {
+            for &pipeline in self.pipelines.iter().flatten() {
+                logical.destroy_pipeline(pipeline, None);
+            }
+            logical.destroy_pipeline_layout(self.layout, None);
+            logical.destroy_descriptor_set_layout(self.camera_layout, None);
+            logical.destroy_descriptor_set_layout(self.material_layout, None);
+        }
+    }
+}
+
+pub enum Binding<'a> {
+    StorageBuffer(&'a Buffer),
+    UniformBuffer(&'a Buffer),
+    StorageImage(vk::ImageView)
+}
+
+impl Binding<'_> {
+    pub fn descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            Binding::StorageBuffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+            Binding::UniformBuffer(_) => vk::DescriptorType::UNIFORM_BUFFER,
+            Binding::StorageImage(_) => vk::DescriptorType::STORAGE_IMAGE,
+        }
+    }
+}
+
+pub struct ComputePipeline {
+    pub device: Arc<Device>,
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_types: Vec<vk::DescriptorType>
+}
+
+impl ComputePipeline {
+    pub fn new(
+        device: &Arc<Device>,
+        shader: &Shader,
+        descriptor_types: &[vk::DescriptorType],
+        push_constant_size: u32,
+        max_sets: u32)
+    -> Self {
+        let set_layout_bindings: Vec<_> = descriptor_types.iter()
+            .enumerate()
+            .map(|(i, &ty)| vk::DescriptorSetLayoutBinding::builder()
+                .binding(i as u32)
+                .descriptor_type(ty)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build())
+            .collect();
+
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&set_layout_bindings);
+
+        let set_layout = unsafe {
+            device.logical.create_descriptor_set_layout(&set_layout_info, None).unwrap()
+        };
+
+        let set_layouts = [set_layout];
+        let push_constant_ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: push_constant_size
+            }
+        ];
+
+        let mut layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts);
+        if push_constant_size > 0 {
+            layout_info = layout_info.push_constant_ranges(&push_constant_ranges);
+        }
+
+        let layout = unsafe {
+            device.logical.create_pipeline_layout(&layout_info, None).unwrap()
+        };
+
+        let info = vk::ComputePipelineCreateInfo::builder()
+            .stage(shader.stage_info)
+            .layout(layout);
+
         let pipeline = unsafe {
-            logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &[info.build()], None).unwrap()
+            device.logical.create_compute_pipelines(device.pipeline_cache, &[info.build()], None).unwrap()
         }[0];
 
-        (pipeline, layout)
+        let descriptor_pool = Self::new_descriptor_pool(device, descriptor_types, max_sets);
+
+        Self {
+            device: device.clone(),
+            pipeline,
+            layout,
+            set_layout,
+            descriptor_pool,
+            descriptor_types: descriptor_types.to_vec()
+        }
     }
 
-    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
-        logical_device.destroy_pipeline(self.graphics, None);
-        logical_device.destroy_pipeline_layout(self.layout, None);
+    pub fn set_name(&self, name: &str) {
+        self.device.set_object_name(self.pipeline, name);
+        self.device.set_object_name(self.layout, &format!("{} layout", name));
+        self.device.set_object_name(self.set_layout, &format!("{} set layout", name));
+        self.device.set_object_name(self.descriptor_pool, &format!("{} descriptor pool", name));
     }
-}
\ No newline at end of file
+
+    fn new_descriptor_pool(device: &Device, descriptor_types: &[vk::DescriptorType], max_sets: u32) -> vk::DescriptorPool {
+        let mut pool_sizes: Vec<vk::DescriptorPoolSize> = vec![];
+        for &ty in descriptor_types {
+            match pool_sizes.iter_mut().find(|size| size.ty == ty) {
+                Some(size) => size.descriptor_count += max_sets,
+                None => pool_sizes.push(vk::DescriptorPoolSize {
+                    ty,
+                    descriptor_count: max_sets
+                }),
+            }
+        }
+
+        let info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(max_sets)
+            .pool_sizes(&pool_sizes);
+
+        unsafe {
+            device.logical.create_descriptor_pool(&info, None).unwrap()
+        }
+    }
+
+    pub fn new_descriptor_set(&self, bindings: &[Binding]) -> vk::DescriptorSet {
+        assert_eq!(bindings.len(), self.descriptor_types.len(), "binding count does not match the pipeline layout");
+
+        let set_layouts = [self.set_layout];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        let set = unsafe {
+            self.device.logical.allocate_descriptor_sets(&alloc_info).unwrap()
+        }[0];
+
+        let buffer_infos: Vec<_> = bindings.iter()
+            .map(|binding| match binding {
+                Binding::StorageBuffer(buffer) | Binding::UniformBuffer(buffer) => [vk::DescriptorBufferInfo {
+                    buffer: buffer.buffer,
+                    offset: 0,
+                    range: vk::WHOLE_SIZE
+                }],
+                Binding::StorageImage(_) => [vk::DescriptorBufferInfo::default()],
+            })
+            .collect();
+
+        let image_infos: Vec<_> = bindings.iter()
+            .map(|binding| match binding {
+                Binding::StorageImage(view) => [vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: *view,
+                    image_layout: vk::ImageLayout::GENERAL
+                }],
+                _ => [vk::DescriptorImageInfo::default()],
+            })
+            .collect();
+
+        let writes: Vec<_> = bindings.iter()
+            .enumerate()
+            .map(|(i, binding)| {
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(i as u32)
+                    .descriptor_type(binding.descriptor_type());
+
+                match binding {
+                    Binding::StorageImage(_) => write.image_info(&image_infos[i]),
+                    _ => write.buffer_info(&buffer_infos[i]),
+                }.build()
+            })
+            .collect();
+
+        unsafe {
+            self.device.logical.update_descriptor_sets(&writes, &[]);
+        }
+
+        set
+    }
+
+    pub fn bind(&self, command_buffer: vk::CommandBuffer, descriptor_set: vk::DescriptorSet, push_constants: &[u8]) {
+        unsafe {
+            self.device.logical.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+
+            self.device.logical.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[]);
+
+            if !push_constants.is_empty() {
+                self.device.logical.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants);
+            }
+        }
+    }
+
+    pub fn dispatch(&self, command_buffer: vk::CommandBuffer, group_counts: [u32; 3]) {
+        unsafe {
+            self.device.logical.cmd_dispatch(command_buffer, group_counts[0], group_counts[1], group_counts[2]);
+        }
+    }
+
+    pub fn dispatch_indirect(&self, command_buffer: vk::CommandBuffer, buffer: &Buffer, offset: vk::DeviceSize) {
+        unsafe {
+            self.device.logical.cmd_dispatch_indirect(command_buffer, buffer.buffer, offset);
+        }
+    }
+
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        let logical = &self.device.logical;
+
+        unsafe {
+            logical.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical.destroy_pipeline(self.pipeline, None);
+            logical.destroy_pipeline_layout(self.layout, None);
+            logical.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
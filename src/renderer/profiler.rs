@@ -0,0 +1,477 @@
+use super::Device;
+
+use ash::vk;
+use std::{collections::VecDeque, fmt, ops::Range, path::Path, sync::Arc};
+
+const STATISTICS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+        | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.as_raw());
+
+//results are written in flag bit order
+const STATISTICS_COUNT: usize = 6;
+
+const HISTORY_LENGTH: usize = 120;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineStatistics {
+    pub input_vertices: u64,
+    pub input_primitives: u64,
+    pub vertex_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_invocations: u64,
+    pub compute_invocations: u64
+}
+
+impl PipelineStatistics {
+    fn from_results(values: [u64; STATISTICS_COUNT]) -> Self {
+        Self {
+            input_vertices: values[0],
+            input_primitives: values[1],
+            vertex_invocations: values[2],
+            clipping_primitives: values[3],
+            fragment_invocations: values[4],
+            compute_invocations: values[5]
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScopeTiming {
+    pub name: String,
+    pub depth: usize,
+    //relative to the start of the frame
+    pub start_ms: f64,
+    pub duration_ms: f64,
+    pub statistics: Option<PipelineStatistics>
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameReport {
+    pub frame: u64,
+    //gpu clock at the start of the frame
+    pub start_ms: f64,
+    pub duration_ms: f64,
+    pub scopes: Vec<ScopeTiming>
+}
+
+impl fmt::Display for FrameReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "gpu frame {}: {:.3} ms", self.frame, self.duration_ms)?;
+
+        for scope in &self.scopes {
+            let name = format!("{}{}", "  ".repeat(scope.depth + 1), scope.name);
+            write!(f, "{:<32} {:>8.3} ms", name, scope.duration_ms)?;
+
+            if let Some(stats) = scope.statistics {
+                write!(f, "  vertices {} primitives {} clipped {} vs {} fs {} cs {}",
+                    stats.input_vertices,
+                    stats.input_primitives,
+                    stats.clipping_primitives,
+                    stats.vertex_invocations,
+                    stats.fragment_invocations,
+                    stats.compute_invocations)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Scope {
+    name: String,
+    depth: usize,
+    statistics: bool
+}
+
+#[derive(Default)]
+struct Slot {
+    scopes: Vec<Scope>,
+    //None for scopes dropped because the slot ran out of queries
+    open: Vec<Option<usize>>,
+    recorded: bool,
+    submitted: bool,
+    //index of the submission, set once submitted
+    frame: u64
+}
+
+//every slot owns a range of queries, one slot per command buffer that may be in flight
+pub struct GpuProfiler {
+    pub device: Arc<Device>,
+    pub timestamp_pool: vk::QueryPool,
+    pub statistics_pool: Option<vk::QueryPool>,
+    pub timestamp_period: f64,
+    pub timestamp_mask: u64,
+    pub max_scopes: usize,
+    slots: Vec<Slot>,
+    history: VecDeque<FrameReport>,
+    //submitted frames so far
+    frame: u64
+}
+
+impl GpuProfiler {
+    pub fn new(device: &Arc<Device>, slot_count: usize, max_scopes: usize, statistics: bool) -> Self {
+        let props = unsafe {
+            device.instance.handle.get_physical_device_properties(device.physical)
+        };
+
+        let valid_bits = device.graphics_family.timestamp_valid_bits;
+        if valid_bits == 0 {
+            log::warn!("graphics queue does not support timestamps, gpu profiling is disabled");
+        }
+
+        let timestamp_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count((slot_count * Self::timestamps_per_slot(max_scopes)) as u32);
+
+        let timestamp_pool = unsafe {
+            device.logical.create_query_pool(&timestamp_info, None).unwrap()
+        };
+
+        let supported = device.features.pipeline_statistics_query == vk::TRUE;
+        if statistics && !supported {
+            log::warn!("the device has no pipeline statistics queries, the profiler only records timestamps");
+        }
+        let statistics = statistics && supported;
+        let statistics_pool = statistics.then(|| {
+            let info = vk::QueryPoolCreateInfo::builder()
+                .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                .query_count((slot_count * max_scopes) as u32)
+                .pipeline_statistics(STATISTICS);
+
+            unsafe {
+                device.logical.create_query_pool(&info, None).unwrap()
+            }
+        });
+
+        device.set_object_name(timestamp_pool, "profiler timestamps");
+        if let Some(pool) = statistics_pool {
+            device.set_object_name(pool, "profiler pipeline statistics");
+        }
+
+        Self {
+            device: device.clone(),
+            timestamp_pool,
+            statistics_pool,
+            timestamp_period: props.limits.timestamp_period as f64,
+            timestamp_mask: if valid_bits >= 64 { u64::MAX } else { (1 << valid_bits) - 1 },
+            max_scopes,
+            slots: (0..slot_count).map(|_| Slot::default()).collect(),
+            history: VecDeque::new(),
+            frame: 0
+        }
+    }
+
+    //a frame and an end timestamp for the whole frame, then a pair per scope
+    fn timestamps_per_slot(max_scopes: usize) -> usize {
+        2 + 2 * max_scopes
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.timestamp_mask != 0
+    }
+
+    fn first_timestamp(&self, slot: usize) -> u32 {
+        (slot * Self::timestamps_per_slot(self.max_scopes)) as u32
+    }
+
+    fn first_statistics(&self, slot: usize) -> u32 {
+        (slot * self.max_scopes) as u32
+    }
+
+    pub fn begin_frame(&mut self, command_buffer: vk::CommandBuffer, slot: usize) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let first_timestamp = self.first_timestamp(slot);
+        let first_statistics = self.first_statistics(slot);
+
+        unsafe {
+            self.device.logical.cmd_reset_query_pool(
+                command_buffer,
+                self.timestamp_pool,
+                first_timestamp,
+                Self::timestamps_per_slot(self.max_scopes) as u32);
+
+            if let Some(pool) = self.statistics_pool {
+                self.device.logical.cmd_reset_query_pool(command_buffer, pool, first_statistics, self.max_scopes as u32);
+            }
+
+            self.device.logical.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                self.timestamp_pool,
+                first_timestamp);
+        }
+
+        self.slots[slot] = Slot::default();
+    }
+
+    pub fn end_frame(&mut self, command_buffer: vk::CommandBuffer, slot: usize) {
+        if !self.is_enabled() {
+            return;
+        }
+        assert!(self.slots[slot].open.is_empty(), "profiler scope was left open");
+
+        unsafe {
+            self.device.logical.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.timestamp_pool,
+                self.first_timestamp(slot) + 1);
+        }
+
+        self.slots[slot].recorded = true;
+    }
+
+    //scopes can nest, but only outermost ones collect pipeline statistics as those queries cannot be nested
+    pub fn begin_scope(&mut self, command_buffer: vk::CommandBuffer, slot: usize, name: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let depth = self.slots[slot].open.len();
+        let index = self.slots[slot].scopes.len();
+        if index >= self.max_scopes {
+            self.slots[slot].open.push(None);
+            return;
+        }
+
+        let statistics = self.statistics_pool.is_some() && depth == 0;
+        unsafe {
+            self.device.logical.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                self.timestamp_pool,
+                self.first_timestamp(slot) + 2 + 2 * index as u32);
+
+            if let Some(pool) = self.statistics_pool.filter(|_| statistics) {
+                self.device.logical.cmd_begin_query(
+                    command_buffer,
+                    pool,
+                    self.first_statistics(slot) + index as u32,
+                    vk::QueryControlFlags::empty());
+            }
+        }
+
+        let slot = &mut self.slots[slot];
+        slot.scopes.push(Scope {
+            name: name.to_owned(),
+            depth,
+            statistics
+        });
+        slot.open.push(Some(index));
+    }
+
+    pub fn end_scope(&mut self, command_buffer: vk::CommandBuffer, slot: usize) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let index = match self.slots[slot].open.pop().expect("no profiler scope is open") {
+            Some(index) => index,
+            None => return,
+        };
+
+        unsafe {
+            if let Some(pool) = self.statistics_pool.filter(|_| self.slots[slot].scopes[index].statistics) {
+                self.device.logical.cmd_end_query(command_buffer, pool, self.first_statistics(slot) + index as u32);
+            }
+
+            self.device.logical.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.timestamp_pool,
+                self.first_timestamp(slot) + 3 + 2 * index as u32);
+        }
+    }
+
+    //the slot's command buffer was submitted, its results can be collected once it finished
+    pub fn submitted(&mut self, slot: usize) {
+        let slot = &mut self.slots[slot];
+        slot.submitted = slot.recorded;
+        if slot.submitted {
+            slot.frame = self.frame;
+            self.frame += 1;
+        }
+    }
+
+    //reads back the results of the slot's last submission, which has to have finished
+    pub fn collect(&mut self, slot: usize) {
+        if !self.slots[slot].submitted {
+            return;
+        }
+        self.slots[slot].submitted = false;
+
+        let scope_count = self.slots[slot].scopes.len();
+
+        let mut timestamps = vec![0u64; 2 + 2 * scope_count];
+        let result = unsafe {
+            self.device.logical.get_query_pool_results(
+                self.timestamp_pool,
+                self.first_timestamp(slot),
+                timestamps.len() as u32,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64)
+        };
+        if result.is_err() {
+            return;
+        }
+
+        //only the outermost scopes began a query, reading the others would never be ready
+        let mut statistics = vec![None; scope_count];
+        if let Some(pool) = self.statistics_pool {
+            for run in statistics_runs(&self.slots[slot].scopes) {
+                let mut values = vec![[0u64; STATISTICS_COUNT]; run.len()];
+                let read = unsafe {
+                    self.device.logical.get_query_pool_results(
+                        pool,
+                        self.first_statistics(slot) + run.start as u32,
+                        run.len() as u32,
+                        &mut values,
+                        vk::QueryResultFlags::TYPE_64)
+                };
+                if read.is_ok() {
+                    for (i, values) in run.zip(values) {
+                        statistics[i] = Some(PipelineStatistics::from_results(values));
+                    }
+                }
+            }
+        }
+
+        let to_ms = |ticks: u64| (ticks & self.timestamp_mask) as f64 * self.timestamp_period / 1_000_000.0;
+        let frame_start = to_ms(timestamps[0]);
+
+        let scopes = self.slots[slot].scopes.iter()
+            .zip(statistics)
+            .enumerate()
+            .map(|(i, (scope, statistics))| {
+                let start = to_ms(timestamps[2 + 2 * i]);
+                let end = to_ms(timestamps[3 + 2 * i]);
+
+                ScopeTiming {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    start_ms: start - frame_start,
+                    duration_ms: end - start,
+                    statistics
+                }
+            })
+            .collect();
+
+        self.history.push_back(FrameReport {
+            frame: self.slots[slot].frame,
+            start_ms: frame_start,
+            duration_ms: to_ms(timestamps[1]) - frame_start,
+            scopes
+        });
+        if self.history.len() > HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn last_report(&self) -> Option<&FrameReport> {
+        self.history.back()
+    }
+
+    pub fn reports(&self) -> impl Iterator<Item = &FrameReport> {
+        self.history.iter()
+    }
+
+    //trace event format, loadable in chrome://tracing and perfetto
+    pub fn chrome_trace(&self) -> String {
+        let origin = self.history.front().map_or(0.0, |report| report.start_ms);
+
+        let mut events = vec![];
+        for report in &self.history {
+            let frame_start = report.start_ms - origin;
+            events.push(trace_event(&format!("frame {}", report.frame), frame_start, report.duration_ms));
+
+            for scope in &report.scopes {
+                events.push(trace_event(&scope.name, frame_start + scope.start_ms, scope.duration_ms));
+            }
+        }
+
+        format!("{{\"traceEvents\":[{}],\"displayTimeUnit\":\"ms\"}}", events.join(","))
+    }
+
+    pub fn write_chrome_trace(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.chrome_trace())?;
+        Ok(())
+    }
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.logical.destroy_query_pool(self.timestamp_pool, None);
+            if let Some(pool) = self.statistics_pool {
+                self.device.logical.destroy_query_pool(pool, None);
+            }
+        }
+    }
+}
+
+//the scopes that began a pipeline statistics query, as runs of consecutive query indices
+fn statistics_runs(scopes: &[Scope]) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = vec![];
+    for (i, scope) in scopes.iter().enumerate() {
+        if !scope.statistics {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.end == i => run.end += 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+    runs
+}
+
+//timestamps in the trace are in microseconds, nested scopes stack up on the same thread
+fn trace_event(name: &str, start_ms: f64, duration_ms: f64) -> String {
+    let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+
+    format!(
+        "{{\"name\":\"{}\",\"cat\":\"gpu\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\"ts\":{:.3},\"dur\":{:.3}}}",
+        name, start_ms * 1000.0, duration_ms * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(depth: usize, statistics: bool) -> Scope {
+        Scope {
+            name: String::new(),
+            depth,
+            statistics
+        }
+    }
+
+    #[test]
+    fn nested_scopes_are_skipped_when_reading_statistics() {
+        let scopes = [
+            scope(0, true),
+            scope(1, false),
+            scope(2, false),
+            scope(0, true),
+            scope(0, true),
+            scope(1, false)
+        ];
+
+        assert_eq!(statistics_runs(&scopes), [0..1, 3..5]);
+    }
+
+    #[test]
+    fn timestamp_only_profilers_read_no_statistics() {
+        let scopes = [scope(0, false), scope(1, false)];
+
+        assert!(statistics_runs(&scopes).is_empty());
+        assert!(statistics_runs(&[]).is_empty());
+    }
+}
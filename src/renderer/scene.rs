@@ -0,0 +1,503 @@
+use super::Device;
+use super::mesh::{Mesh, MeshData};
+use super::texture::{SamplerDesc, Texture, TextureData};
+use super::material::{AlphaMode, Material, TextureRef};
+
+use ash::vk;
+use anyhow::{Result, anyhow};
+use glam::{Mat4, Quat, Vec3};
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    //the aspect ratio follows the viewport when unset, an unset zfar is infinite
+    Perspective { yfov: f32, aspect_ratio: Option<f32>, znear: f32, zfar: Option<f32> },
+    //half extents
+    Orthographic { xmag: f32, ymag: f32, znear: f32, zfar: f32 }
+}
+
+#[derive(Clone, Debug)]
+pub struct SceneCamera {
+    pub name: String,
+    pub projection: Projection
+}
+
+//KHR_lights_punctual, lights shine down their node's -z axis
+#[derive(Clone, Copy, Debug)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot { inner_cone_angle: f32, outer_cone_angle: f32 }
+}
+
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub name: String,
+    pub kind: LightKind,
+    pub color: Vec3,
+    //lux for directional lights, candela otherwise
+    pub intensity: f32,
+    //infinite when unset
+    pub range: Option<f32>
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    //kept up to date by update_transforms
+    pub world: Mat4,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub light: Option<usize>
+}
+
+//a submesh placed in the world, ready to be recorded
+pub struct DrawItem<'a> {
+    pub mesh: &'a Mesh,
+    pub submesh: usize,
+    //into the scene's materials
+    pub material_index: usize,
+    pub material: &'a Material,
+    pub transform: Mat4
+}
+
+//every index refers to the scene's own vectors
+#[derive(Default)]
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>,
+    pub meshes: Vec<Mesh>,
+    //material slots of the meshes' submeshes, the last one is the default material
+    pub materials: Vec<Material>,
+    pub textures: Vec<Arc<Texture>>,
+    pub cameras: Vec<SceneCamera>,
+    pub lights: Vec<Light>
+}
+
+impl Scene {
+    //imports the default scene, or the first one when there is no default
+    pub fn load(device: &Arc<Device>, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (document, buffers, images) = gltf::import(path)?;
+
+        let gltf_scene = document.default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| anyhow!("{:?} has no scenes", path))?;
+
+        let mut textures = TextureCache::new(device, &images);
+
+        let mut materials: Vec<Material> = document.materials()
+            .map(|material| import_material(&material, &mut textures))
+            .collect::<Result<_>>()?;
+        let default_material = materials.len();
+        materials.push(Material::default());
+
+        //meshes without triangles are left out, their nodes don't draw
+        let mut meshes = vec![];
+        let mut mesh_indices = vec![None; document.meshes().len()];
+        for mesh in document.meshes() {
+            let mut data = MeshData::from_gltf_mesh(&mesh, &buffers)?;
+            for submesh in &mut data.submeshes {
+                submesh.material = Some(submesh.material.unwrap_or(default_material));
+            }
+
+            match Mesh::new(device, &data) {
+                Ok(gpu_mesh) => {
+                    gpu_mesh.set_name(mesh.name().unwrap_or(&format!("mesh {}", mesh.index())));
+                    mesh_indices[mesh.index()] = Some(meshes.len());
+                    meshes.push(gpu_mesh);
+                },
+                Err(error) => log::warn!("skipping mesh {:?}: {}", mesh.name(), error),
+            }
+        }
+
+        let cameras = document.cameras().map(|camera| SceneCamera {
+            name: camera.name().unwrap_or_default().to_owned(),
+            projection: match camera.projection() {
+                gltf::camera::Projection::Perspective(p) => Projection::Perspective {
+                    yfov: p.yfov(),
+                    aspect_ratio: p.aspect_ratio(),
+                    znear: p.znear(),
+                    zfar: p.zfar()
+                },
+                gltf::camera::Projection::Orthographic(o) => Projection::Orthographic {
+                    xmag: o.xmag(),
+                    ymag: o.ymag(),
+                    znear: o.znear(),
+                    zfar: o.zfar()
+                },
+            }
+        }).collect();
+
+        let lights = document.lights().into_iter().flatten().map(|light| Light {
+            name: light.name().unwrap_or_default().to_owned(),
+            kind: match light.kind() {
+                gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+                gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+                gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } =>
+                    LightKind::Spot { inner_cone_angle, outer_cone_angle },
+            },
+            color: Vec3::from(light.color()),
+            intensity: light.intensity(),
+            range: light.range()
+        }).collect();
+
+        //every document node is kept so indices match, only the scene's are reachable from the roots
+        let mut nodes: Vec<Node> = document.nodes().map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+
+            Node {
+                name: node.name().unwrap_or_default().to_owned(),
+                transform: Transform {
+                    translation: Vec3::from(translation),
+                    rotation: Quat::from_array(rotation),
+                    scale: Vec3::from(scale)
+                },
+                world: Mat4::IDENTITY,
+                parent: None,
+                children: node.children().map(|child| child.index()).collect(),
+                mesh: node.mesh().and_then(|mesh| mesh_indices[mesh.index()]),
+                camera: node.camera().map(|camera| camera.index()),
+                light: node.light().map(|light| light.index())
+            }
+        }).collect();
+
+        for i in 0..nodes.len() {
+            for child in nodes[i].children.clone() {
+                nodes[child].parent = Some(i);
+            }
+        }
+
+        let mut scene = Self {
+            nodes,
+            roots: gltf_scene.nodes().map(|node| node.index()).collect(),
+            meshes,
+            materials,
+            textures: textures.into_textures(),
+            cameras,
+            lights
+        };
+        scene.update_transforms();
+
+        Ok(scene)
+    }
+
+    //recomputes world matrices after node transforms changed
+    pub fn update_transforms(&mut self) {
+        let mut stack: Vec<(usize, Mat4)> = self.roots.iter().map(|&root| (root, Mat4::IDENTITY)).collect();
+
+        while let Some((index, parent)) = stack.pop() {
+            let world = parent * self.nodes[index].transform.matrix();
+            self.nodes[index].world = world;
+
+            stack.extend(self.nodes[index].children.iter().map(|&child| (child, world)));
+        }
+    }
+
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    //nodes reachable from the roots that carry a camera, with their world matrices
+    pub fn camera_nodes(&self) -> impl Iterator<Item = (&SceneCamera, Mat4)> + '_ {
+        self.reachable().filter_map(|node| node.camera.map(|camera| (&self.cameras[camera], node.world)))
+    }
+
+    pub fn light_nodes(&self) -> impl Iterator<Item = (&Light, Mat4)> + '_ {
+        self.reachable().filter_map(|node| node.light.map(|light| (&self.lights[light], node.world)))
+    }
+
+    //every submesh of every reachable node, opaque ones first
+    pub fn draws(&self) -> Vec<DrawItem<'_>> {
+        let mut draws: Vec<DrawItem> = self.reachable()
+            .filter_map(|node| node.mesh.map(|mesh| (&self.meshes[mesh], node.world)))
+            .flat_map(|(mesh, transform)| mesh.submeshes.iter().enumerate().map(move |(submesh, data)| {
+                let material_index = data.material.unwrap_or(self.materials.len() - 1);
+                DrawItem {
+                    mesh,
+                    submesh,
+                    material_index,
+                    material: &self.materials[material_index],
+                    transform
+                }
+            }))
+            .collect();
+
+        draws.sort_by_key(|draw| draw.material.is_transparent());
+        draws
+    }
+
+    fn reachable(&self) -> impl Iterator<Item = &Node> + '_ {
+        let mut stack = self.roots.clone();
+
+        std::iter::from_fn(move || {
+            let index = stack.pop()?;
+            stack.extend(&self.nodes[index].children);
+            Some(&self.nodes[index])
+        })
+    }
+}
+
+fn import_material(material: &gltf::Material, textures: &mut TextureCache) -> Result<Material> {
+    let pbr = material.pbr_metallic_roughness();
+
+    let mut texture = |info: Option<(gltf::Texture, u32)>, srgb| -> Result<Option<TextureRef>> {
+        info.map(|(texture, uv_set)| Ok(TextureRef {
+            texture: textures.get(&texture, srgb)?,
+            uv_set
+        })).transpose()
+    };
+
+    Ok(Material {
+        name: material.name().unwrap_or_default().to_owned(),
+        base_color: pbr.base_color_factor().into(),
+        base_color_texture: texture(pbr.base_color_texture().map(|info| (info.texture(), info.tex_coord())), true)?,
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        metallic_roughness_texture: texture(pbr.metallic_roughness_texture().map(|info| (info.texture(), info.tex_coord())), false)?,
+        normal_texture: texture(material.normal_texture().map(|info| (info.texture(), info.tex_coord())), false)?,
+        normal_scale: material.normal_texture().map_or(1.0, |info| info.scale()),
+        occlusion_texture: texture(material.occlusion_texture().map(|info| (info.texture(), info.tex_coord())), false)?,
+        occlusion_strength: material.occlusion_texture().map_or(1.0, |info| info.strength()),
+        emissive: material.emissive_factor().into(),
+        emissive_texture: texture(material.emissive_texture().map(|info| (info.texture(), info.tex_coord())), true)?,
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided()
+    })
+}
+
+//uploads each image once per color space and sampler it's used with
+struct TextureCache<'a> {
+    device: &'a Arc<Device>,
+    images: &'a [gltf::image::Data],
+    textures: Vec<Arc<Texture>>,
+    uploaded: HashMap<(usize, bool, Option<usize>), usize>
+}
+
+impl<'a> TextureCache<'a> {
+    fn new(device: &'a Arc<Device>, images: &'a [gltf::image::Data]) -> Self {
+        Self {
+            device,
+            images,
+            textures: vec![],
+            uploaded: HashMap::new()
+        }
+    }
+
+    fn get(&mut self, texture: &gltf::Texture, srgb: bool) -> Result<Arc<Texture>> {
+        let image = texture.source().index();
+        let sampler = texture.sampler();
+        let key = (image, srgb, sampler.index());
+        if let Some(&index) = self.uploaded.get(&key) {
+            return Ok(self.textures[index].clone());
+        }
+
+        let data = &self.images[image];
+        let texture = Texture::with_sampler(self.device, TextureData {
+            format: if srgb { vk::Format::R8G8B8A8_SRGB } else { vk::Format::R8G8B8A8_UNORM },
+            extent: vk::Extent2D { width: data.width, height: data.height },
+            levels: vec![to_rgba8(data)]
+        }, sampler_desc(&sampler))?;
+        texture.set_name(&format!("gltf image {}", image));
+
+        self.uploaded.insert(key, self.textures.len());
+        self.textures.push(Arc::new(texture));
+        Ok(self.textures[self.textures.len() - 1].clone())
+    }
+
+    fn into_textures(self) -> Vec<Arc<Texture>> {
+        self.textures
+    }
+}
+
+//unset filters are left to the renderer, which filters linearly
+fn sampler_desc(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST),
+        Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST),
+        Some(MinFilter::NearestMipmapLinear) => (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR),
+        Some(MinFilter::LinearMipmapLinear) | None => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+    };
+
+    SamplerDesc {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            Some(MagFilter::Linear) | None => vk::Filter::LINEAR,
+        },
+        min_filter,
+        mipmap_mode,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t())
+    }
+}
+
+//wider channels are narrowed to 8 bits, missing ones are filled in
+fn to_rgba8(data: &gltf::image::Data) -> Vec<u8> {
+    use gltf::image::Format;
+
+    let (channels, channel_size) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let channel = |bytes: &[u8]| match channel_size {
+        1 => bytes[0],
+        2 => (u16::from_le_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+        _ => (f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0).round() as u8,
+    };
+
+    let mut rgba = Vec::with_capacity((data.width * data.height * 4) as usize);
+    for pixel in data.pixels.chunks_exact(channels * channel_size) {
+        let values: Vec<u8> = pixel.chunks_exact(channel_size).map(channel).collect();
+        rgba.extend_from_slice(&match channels {
+            1 => [values[0], values[0], values[0], 255],
+            2 => [values[0], values[1], 0, 255],
+            3 => [values[0], values[1], values[2], 255],
+            _ => [values[0], values[1], values[2], values[3]],
+        });
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, transform: Transform, parent: Option<usize>, children: Vec<usize>) -> Node {
+        Node {
+            name: name.to_owned(),
+            transform,
+            world: Mat4::IDENTITY,
+            parent,
+            children,
+            mesh: None,
+            camera: None,
+            light: None
+        }
+    }
+
+    #[test]
+    fn world_matrices_apply_the_parent_after_the_child() {
+        let root = Transform { translation: Vec3::X, scale: Vec3::splat(2.0), ..Default::default() };
+        let child = Transform { translation: Vec3::Y, rotation: Quat::from_rotation_z(90f32.to_radians()), ..Default::default() };
+        let grandchild = Transform { translation: Vec3::X, ..Default::default() };
+        let loose = Transform { translation: Vec3::Z, ..Default::default() };
+
+        let mut scene = Scene {
+            nodes: vec![
+                node("root", root, None, vec![1]),
+                node("child", child, Some(0), vec![2]),
+                node("grandchild", grandchild, Some(1), vec![]),
+                node("loose", loose, None, vec![]),
+            ],
+            roots: vec![0],
+            ..Default::default()
+        };
+        scene.update_transforms();
+
+        assert_eq!(scene.nodes[1].world, root.matrix() * child.matrix());
+        assert_eq!(scene.nodes[2].world, root.matrix() * child.matrix() * grandchild.matrix());
+
+        //the child's offset is scaled by the root, and the grandchild's is turned by the child
+        let origin = |index: usize| scene.nodes[index].world.transform_point3(Vec3::ZERO);
+        assert!(origin(1).abs_diff_eq(Vec3::new(1.0, 2.0, 0.0), 1e-5));
+        assert!(origin(2).abs_diff_eq(Vec3::new(1.0, 4.0, 0.0), 1e-5));
+
+        //nodes outside the roots are left alone
+        assert_eq!(scene.nodes[3].world, Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn gltf_filters_map_to_sampler_filters_and_mipmap_modes() {
+        let json = br#"{
+            "asset": { "version": "2.0" },
+            "samplers": [
+                { "magFilter": 9728, "minFilter": 9728, "wrapS": 33071, "wrapT": 33648 },
+                { "magFilter": 9729, "minFilter": 9985 },
+                { "minFilter": 9986 },
+                {}
+            ]
+        }"#;
+        let document = gltf::Gltf::from_slice(json).unwrap();
+        let samplers: Vec<SamplerDesc> = document.samplers().map(|sampler| sampler_desc(&sampler)).collect();
+
+        assert_eq!(samplers[0], SamplerDesc {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::MIRRORED_REPEAT
+        });
+        assert_eq!((samplers[1].mag_filter, samplers[1].min_filter, samplers[1].mipmap_mode),
+            (vk::Filter::LINEAR, vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST));
+        assert_eq!((samplers[2].min_filter, samplers[2].mipmap_mode),
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR));
+        assert_eq!(samplers[3], SamplerDesc::default());
+    }
+
+    #[test]
+    fn wide_channels_are_narrowed_and_missing_ones_filled() {
+        let r16 = gltf::image::Data {
+            pixels: [0x1234u16, 0xffff].iter().flat_map(|value| value.to_le_bytes()).collect(),
+            format: gltf::image::Format::R16,
+            width: 2,
+            height: 1
+        };
+        assert_eq!(to_rgba8(&r16), [0x12, 0x12, 0x12, 255, 255, 255, 255, 255]);
+
+        let rgb32 = gltf::image::Data {
+            pixels: [0.5f32, -1.0, 2.0].iter().flat_map(|value| value.to_le_bytes()).collect(),
+            format: gltf::image::Format::R32G32B32FLOAT,
+            width: 1,
+            height: 1
+        };
+        assert_eq!(to_rgba8(&rgb32), [128, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,280 @@
+use super::{Buffer, Device, Frame, Renderer};
+use super::pipeline::{MeshPipeline, MATERIAL_TEXTURES};
+use super::scene::Scene;
+use super::camera::{Camera, CameraUniform};
+use super::material::{Material, MaterialParams};
+use super::texture::{Texture, TextureData};
+use super::mesh::as_bytes;
+use super::graph::PassContext;
+use super::deletion::{DeletionQueue, Retired};
+
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use std::{cell::RefCell, collections::HashMap, mem::{size_of, ManuallyDrop}, rc::Rc, sync::Arc};
+use winit::window::WindowId;
+
+const SETS_PER_POOL: u32 = 64;
+
+//draws a scene from inside a pass rendering to the backbuffer and the depth buffer
+pub struct SceneRenderer {
+    pub device: Arc<Device>,
+    pub pipeline: Arc<MeshPipeline>,
+    pub scene: Scene,
+    //in the scene's material order
+    pub material_sets: Vec<vk::DescriptorSet>,
+    pub material_buffers: Vec<Buffer>,
+    //per window and swapchain image, rewritten once the image's last frame is done with them
+    pub cameras: HashMap<(WindowId, usize), (Buffer, vk::DescriptorSet)>,
+    //bound in place of the textures a material doesn't have, taken out when retired
+    pub white: ManuallyDrop<Texture>,
+    pub flat_normal: ManuallyDrop<Texture>,
+    //a new one is added whenever the last one runs out
+    pub descriptor_pools: Vec<vk::DescriptorPool>,
+    pub deletion_queue: Rc<RefCell<DeletionQueue>>
+}
+
+impl SceneRenderer {
+    pub fn new(renderer: &Renderer, scene: Scene) -> Self {
+        let device = &renderer.device;
+
+        let pixel = |rgba: [u8; 4]| TextureData {
+            format: vk::Format::R8G8B8A8_UNORM,
+            extent: vk::Extent2D { width: 1, height: 1 },
+            levels: vec![rgba.to_vec()]
+        };
+        let white = Texture::new(device, pixel([255, 255, 255, 255])).unwrap();
+        white.set_name("white texture");
+        let flat_normal = Texture::new(device, pixel([128, 128, 255, 255])).unwrap();
+        flat_normal.set_name("flat normal texture");
+
+        let mut renderer = Self {
+            device: device.clone(),
+            pipeline: renderer.mesh_pipeline.clone(),
+            scene,
+            material_sets: vec![],
+            material_buffers: vec![],
+            cameras: HashMap::new(),
+            white: ManuallyDrop::new(white),
+            flat_normal: ManuallyDrop::new(flat_normal),
+            descriptor_pools: vec![],
+            deletion_queue: renderer.deletion_queue.clone()
+        };
+
+        for i in 0..renderer.scene.materials.len() {
+            let material = renderer.scene.materials[i].clone();
+            let (buffer, set) = renderer.new_material_set(&material);
+            renderer.material_buffers.push(buffer);
+            renderer.material_sets.push(set);
+        }
+
+        renderer
+    }
+
+    //call from the pass the frame's app callback records into
+    pub fn draw(&mut self, frame: &Frame, pass: &PassContext, camera: &Camera) {
+        let camera_set = self.camera_set(frame.window, pass.frame_index, camera);
+        let logical = &pass.device.logical;
+        let command_buffer = pass.command_buffer;
+
+        unsafe {
+            logical.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.layout,
+                0,
+                &[camera_set],
+                &[]);
+        }
+
+        let mut bound_pipeline = None;
+        let mut bound_material = None;
+        let mut bound_mesh = None;
+        for draw in self.scene.draws() {
+            let pipeline = self.pipeline.pipeline(draw.material);
+            if bound_pipeline != Some(pipeline) {
+                unsafe {
+                    logical.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                }
+                bound_pipeline = Some(pipeline);
+            }
+
+            if bound_material != Some(draw.material_index) {
+                unsafe {
+                    logical.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipeline.layout,
+                        1,
+                        &[self.material_sets[draw.material_index]],
+                        &[]);
+                }
+                bound_material = Some(draw.material_index);
+            }
+
+            if !bound_mesh.is_some_and(|mesh| std::ptr::eq(mesh, draw.mesh)) {
+                draw.mesh.bind(command_buffer);
+                bound_mesh = Some(draw.mesh);
+            }
+
+            unsafe {
+                logical.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline.layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    as_bytes(&draw.transform.to_cols_array()));
+            }
+            draw.mesh.draw_submesh(command_buffer, draw.submesh);
+        }
+    }
+
+    fn camera_set(&mut self, window: WindowId, image_index: usize, camera: &Camera) -> vk::DescriptorSet {
+        let key = (window, image_index);
+        if !self.cameras.contains_key(&key) {
+            let buffer = Buffer::new(
+                &self.device,
+                size_of::<CameraUniform>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu);
+            buffer.set_name(&format!("camera {}", image_index));
+
+            let set = self.allocate_set(self.pipeline.camera_layout);
+            self.write_set(set, &buffer, &[]);
+            self.cameras.insert(key, (buffer, set));
+        }
+
+        let (buffer, set) = self.cameras.get_mut(&key).unwrap();
+        buffer.write(0, as_bytes(&[camera.uniform()]));
+        *set
+    }
+
+    fn new_material_set(&mut self, material: &Material) -> (Buffer, vk::DescriptorSet) {
+        let mut buffer = Buffer::new(
+            &self.device,
+            size_of::<MaterialParams>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu);
+        buffer.write(0, as_bytes(&[material.params()]));
+        buffer.set_name(&format!("material {}", material.name));
+
+        let set = self.allocate_set(self.pipeline.material_layout);
+
+        let textures: Vec<&Texture> = [
+            (&material.base_color_texture, &*self.white),
+            (&material.metallic_roughness_texture, &*self.white),
+            (&material.normal_texture, &*self.flat_normal),
+            (&material.occlusion_texture, &*self.white),
+            (&material.emissive_texture, &*self.white),
+        ].into_iter()
+            .map(|(texture, fallback)| texture.as_ref().map_or(fallback, |texture| texture.texture.as_ref()))
+            .collect();
+        self.write_set(set, &buffer, &textures);
+
+        (buffer, set)
+    }
+
+    //the buffer goes to binding 0, the textures follow
+    fn write_set(&self, set: vk::DescriptorSet, buffer: &Buffer, textures: &[&Texture]) {
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer: buffer.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE
+        }];
+        let image_infos: Vec<_> = textures.iter()
+            .map(|texture| [vk::DescriptorImageInfo {
+                sampler: texture.sampler,
+                image_view: texture.view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            }])
+            .collect();
+
+        let mut writes = vec![
+            vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&buffer_info)
+                .build()
+        ];
+        writes.extend(image_infos.iter().enumerate().map(|(i, image_info)| vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(i as u32 + 1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(image_info)
+            .build()));
+
+        unsafe {
+            self.device.logical.update_descriptor_sets(&writes, &[]);
+        }
+    }
+
+    fn allocate_set(&mut self, layout: vk::DescriptorSetLayout) -> vk::DescriptorSet {
+        let set_layouts = [layout];
+        if let Some(&pool) = self.descriptor_pools.last() {
+            let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&set_layouts);
+
+            match unsafe { self.device.logical.allocate_descriptor_sets(&alloc_info) } {
+                Ok(sets) => return sets[0],
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {},
+                Err(error) => panic!("failed to allocate a descriptor set: {}", error),
+            }
+        }
+
+        let pool = self.new_descriptor_pool();
+        self.descriptor_pools.push(pool);
+
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+
+        let sets = unsafe {
+            self.device.logical.allocate_descriptor_sets(&alloc_info).unwrap()
+        };
+        sets[0]
+    }
+
+    fn new_descriptor_pool(&self) -> vk::DescriptorPool {
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: SETS_PER_POOL
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: SETS_PER_POOL * MATERIAL_TEXTURES as u32
+            },
+        ];
+
+        let info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(SETS_PER_POOL)
+            .pool_sizes(&pool_sizes);
+
+        let pool = unsafe {
+            self.device.logical.create_descriptor_pool(&info, None).unwrap()
+        };
+        self.device.set_object_name(pool, &format!("scene descriptor pool {}", self.descriptor_pools.len()));
+        pool
+    }
+}
+
+//the sets, buffers and textures may still be used by frames in flight
+impl Drop for SceneRenderer {
+    fn drop(&mut self) {
+        let mut deletion_queue = self.deletion_queue.borrow_mut();
+
+        for pool in self.descriptor_pools.drain(..) {
+            deletion_queue.push(Retired::DescriptorPool(pool));
+        }
+        for buffer in self.material_buffers.drain(..).chain(self.cameras.drain().map(|(_, (buffer, _))| buffer)) {
+            deletion_queue.push(Retired::Buffer(buffer));
+        }
+        deletion_queue.push(Retired::Scene(std::mem::take(&mut self.scene)));
+
+        unsafe {
+            deletion_queue.push(Retired::Texture(ManuallyDrop::take(&mut self.white)));
+            deletion_queue.push(Retired::Texture(ManuallyDrop::take(&mut self.flat_normal)));
+        }
+    }
+}
@@ -1,33 +1,41 @@
+use super::Device;
+
 use ash::vk;
-use std::ffi;
+use std::{ffi, sync::Arc};
 
 pub struct Shader {
+    pub device: Arc<Device>,
     pub module: vk::ShaderModule,
     pub stage_info: vk::PipelineShaderStageCreateInfo
 }
 
 impl Shader {
-    pub fn new(logical_device: &ash::Device, code: &[u32], stage: vk::ShaderStageFlags, entry_name: &ffi::CStr) -> Self {
+    pub fn new(device: &Arc<Device>, code: &[u32], stage: vk::ShaderStageFlags, entry_name: &ffi::CStr) -> Self {
         let module_info = vk::ShaderModuleCreateInfo::builder()
             .code(code);
 
         let module = unsafe {
-            logical_device.create_shader_module(&module_info, None).unwrap()
+            device.logical.create_shader_module(&module_info, None).unwrap()
         };
 
         let stage_info = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(stage)    
+            .stage(stage)
             .module(module)
-            .name(&entry_name)
+            .name(entry_name)
             .build();
-            
+
         Self {
+            device: device.clone(),
             module,
             stage_info
         }
     }
+}
 
-    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
-        logical_device.destroy_shader_module(self.module, None);
+impl Drop for Shader {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.logical.destroy_shader_module(self.module, None);
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,221 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt, time::{Duration, Instant}};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Summary {
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "avg {:.2} min {:.2} max {:.2} p50 {:.2} p95 {:.2} p99 {:.2} ms",
+            self.avg_ms, self.min_ms, self.max_ms, self.p50_ms, self.p95_ms, self.p99_ms)
+    }
+}
+
+//the last capacity samples, at least one
+pub struct Rolling {
+    samples: VecDeque<Duration>,
+    capacity: usize
+}
+
+impl Rolling {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity
+        }
+    }
+
+    pub fn push(&mut self, sample: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn summary(&self) -> Summary {
+        if self.samples.is_empty() {
+            return Summary::default();
+        }
+
+        let mut sorted: Vec<f64> = self.samples.iter().map(|sample| sample.as_secs_f64() * 1000.0).collect();
+        sorted.sort_by(f64::total_cmp);
+
+        //nearest rank
+        let percentile = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+
+        Summary {
+            min_ms: sorted[0],
+            avg_ms: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            max_ms: sorted[sorted.len() - 1],
+            p50_ms: percentile(0.50),
+            p95_ms: percentile(0.95),
+            p99_ms: percentile(0.99)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsDisplay {
+    None,
+    Title,
+    Log
+}
+
+impl StatsDisplay {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "none" => Ok(StatsDisplay::None),
+            "title" => Ok(StatsDisplay::Title),
+            "log" => Ok(StatsDisplay::Log),
+            _ => bail!("unknown stats display {:?}", value),
+        }
+    }
+}
+
+pub struct FrameStats {
+    //cpu time from the start of a frame to its present
+    pub frame_time: Rolling,
+    pub acquire_wait: Rolling,
+    //time blocked on the graphics timeline before the frame's resources could be reused
+    pub fence_wait: Rolling,
+    pub present_interval: Rolling,
+    pub frame_count: u64,
+    pub display: StatsDisplay,
+    pub title: String,
+    pub report_interval: Duration,
+    frame_start: Option<Instant>,
+    last_present: Option<Instant>,
+    last_report: Instant
+}
+
+impl FrameStats {
+    //the title is shown in front of the stats when they go to the window title
+    pub fn new(window: usize, display: StatsDisplay, title: &str) -> Self {
+        Self {
+            frame_time: Rolling::new(window),
+            acquire_wait: Rolling::new(window),
+            fence_wait: Rolling::new(window),
+            present_interval: Rolling::new(window),
+            frame_count: 0,
+            display,
+            title: title.to_owned(),
+            report_interval: Duration::from_secs(1),
+            frame_start: None,
+            last_present: None,
+            last_report: Instant::now()
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.frame_start = Some(Instant::now());
+    }
+
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+
+        if let Some(start) = self.frame_start.take() {
+            self.frame_time.push(now - start);
+        }
+        if let Some(last) = self.last_present {
+            self.present_interval.push(now - last);
+        }
+        self.last_present = Some(now);
+
+        self.frame_count += 1;
+    }
+
+    pub fn fps(&self) -> f64 {
+        let interval = self.present_interval.summary().avg_ms;
+        if interval > 0.0 { 1000.0 / interval } else { 0.0 }
+    }
+
+    //updates the title or logs once per report interval
+    pub fn report(&mut self, window: &winit::window::Window) {
+        if self.display == StatsDisplay::None || self.last_report.elapsed() < self.report_interval {
+            return;
+        }
+        self.last_report = Instant::now();
+
+        match self.display {
+            StatsDisplay::Title => {
+                let frame = self.frame_time.summary();
+                window.set_title(&format!("{} - {:.0} fps - cpu {:.2} ms (p99 {:.2} ms)",
+                    self.title, self.fps(), frame.avg_ms, frame.p99_ms));
+            },
+            StatsDisplay::Log => log::info!("{}", self),
+            StatsDisplay::None => {},
+        }
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "[Stats] frame {} at {:.1} fps", self.frame_count, self.fps())?;
+        writeln!(f, "[Stats] cpu frame        {}", self.frame_time.summary())?;
+        writeln!(f, "[Stats] acquire wait     {}", self.acquire_wait.summary())?;
+        writeln!(f, "[Stats] fence wait       {}", self.fence_wait.summary())?;
+        write!(f, "[Stats] present interval {}", self.present_interval.summary())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rolling(samples_ms: &[u64], capacity: usize) -> Rolling {
+        let mut rolling = Rolling::new(capacity);
+        for &ms in samples_ms {
+            rolling.push(Duration::from_millis(ms));
+        }
+        rolling
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let samples: Vec<u64> = (1..=100).rev().collect();
+        let summary = rolling(&samples, 100).summary();
+
+        assert_eq!(summary.min_ms, 1.0);
+        assert_eq!(summary.max_ms, 100.0);
+        assert_eq!(summary.avg_ms, 50.5);
+        assert_eq!(summary.p50_ms, 50.0);
+        assert_eq!(summary.p95_ms, 95.0);
+        assert_eq!(summary.p99_ms, 99.0);
+
+        //ranks round up, so few samples report the slowest ones
+        let summary = rolling(&[10, 20, 30], 3).summary();
+        assert_eq!(summary.p50_ms, 20.0);
+        assert_eq!(summary.p95_ms, 30.0);
+        assert_eq!(summary.p99_ms, 30.0);
+    }
+
+    #[test]
+    fn only_the_last_samples_are_kept() {
+        let summary = rolling(&[100, 1, 2, 3], 3).summary();
+        assert_eq!(summary.max_ms, 3.0);
+        assert_eq!(summary.avg_ms, 2.0);
+
+        //a capacity of 0 still keeps the latest sample instead of growing forever
+        let rolling = rolling(&[5, 6, 7], 0);
+        assert_eq!(rolling.samples.len(), 1);
+        assert_eq!(rolling.summary().p50_ms, 7.0);
+
+        assert!(Rolling::new(4).is_empty());
+        assert_eq!(Rolling::new(4).summary().max_ms, 0.0);
+    }
+}
@@ -1,85 +1,168 @@
 use ash::{vk, extensions::khr};
 use super::Device;
 use super::Window;
+use super::window::Surface;
+use super::sync;
+
+use gpu_allocator::{MemoryLocation, vulkan::{Allocation, AllocationCreateDesc}};
+use std::sync::Arc;
 
 pub struct Swapchain {
+    pub device: Arc<Device>,
+    pub surface: Arc<Surface>,
     pub loader: khr::Swapchain,
     pub swapchain: vk::SwapchainKHR,
 
+    pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
+    //one per image, a frame only waits for the last one rendering to the same image
+    pub depth_images: Vec<(vk::Image, Allocation)>,
+    pub depth_views: Vec<vk::ImageView>,
     pub framebuffers: Vec<vk::Framebuffer>,
     pub extent: vk::Extent2D,
+    pub present_mode: vk::PresentModeKHR,
 
     pub image_available_semaphores: Vec<vk::Semaphore>,
     pub render_finished_semaphores: Vec<vk::Semaphore>,
-    pub start_draw_fences: Vec<vk::Fence>,
+    //graphics timeline values of the last submission per frame slot and per image
+    pub frame_values: Vec<u64>,
+    pub image_values: Vec<u64>,
     pub image_count: usize,
     pub current_image: usize
 }
 
 impl Swapchain {
-    pub fn new(instance: &ash::Instance, device: &Device, window: &Window, render_pass: vk::RenderPass) -> Self {
+    pub fn new(device: &Arc<Device>, window: &Window, render_pass: Option<vk::RenderPass>, present_mode: vk::PresentModeKHR) -> Self {
+        let present_mode = Self::pick_present_mode(device, window, present_mode);
+
+        Self::create(device, window, render_pass, present_mode, vk::SwapchainKHR::null())
+    }
+
+    //after a resize or fullscreen change, the old one has to live until the gpu is done with its images
+    pub fn recreate(&self, window: &Window, render_pass: Option<vk::RenderPass>) -> Self {
+        Self::create(&self.device, window, render_pass, self.present_mode, self.swapchain)
+    }
+
+    fn create(device: &Arc<Device>,
+        window: &Window,
+        render_pass: Option<vk::RenderPass>,
+        present_mode: vk::PresentModeKHR,
+        old_swapchain: vk::SwapchainKHR)
+    -> Self {
         let capabilities = window.surface_capabilities(device.physical);
+        let extent = Self::pick_extent(window, &capabilities);
 
-        let (loader, swapchain) = Self::new_swapchain(instance, device, window, &capabilities);
+        let (loader, swapchain) = Self::new_swapchain(device, window, &capabilities, extent, present_mode, old_swapchain);
 
         let images = unsafe {
             loader.get_swapchain_images(swapchain).unwrap()
         };
-        let image_views = Self::new_image_views(&images, &device.logical);
+        let image_views = Self::new_image_views(&images, &device.logical, window.format.format);
         let image_count = image_views.len();
 
+        let depth_images: Vec<_> = (0..image_count).map(|_| Self::new_depth_image(device, extent)).collect();
+        let depth_views: Vec<_> = depth_images.iter()
+            .map(|&(image, _)| Self::new_depth_view(&device.logical, image, device.depth_format))
+            .collect();
+
         let (image_available_semaphores,
-            render_finished_semaphores,
-            start_draw_fences) = Self::new_syncs(image_count, &device.logical);
+            render_finished_semaphores) = Self::new_syncs(image_count, &device.logical);
 
-        let extent = capabilities.current_extent;
+        device.set_object_name(swapchain, "swapchain");
+        for i in 0..image_count {
+            device.set_object_name(images[i], &format!("swapchain image {}", i));
+            device.set_object_name(image_views[i], &format!("swapchain image view {}", i));
+            device.set_object_name(depth_images[i].0, &format!("depth buffer {}", i));
+            device.set_object_name(depth_views[i], &format!("depth buffer view {}", i));
+            device.set_object_name(image_available_semaphores[i], &format!("image available {}", i));
+            device.set_object_name(render_finished_semaphores[i], &format!("render finished {}", i));
+        }
+
+        //dynamic rendering draws straight into the image views
+        let framebuffers = match render_pass {
+            Some(render_pass) => Self::new_framebuffers(&image_views, &depth_views, &device.logical, extent, render_pass),
+            None => vec![],
+        };
+        for (i, &framebuffer) in framebuffers.iter().enumerate() {
+            device.set_object_name(framebuffer, &format!("swapchain framebuffer {}", i));
+        }
 
-        let framebuffers = Self::new_framebuffers(&image_views, &device.logical, extent, render_pass);
-        
         Self {
+            device: device.clone(),
+            surface: window.surface.clone(),
             loader,
             swapchain,
+            images,
             image_views,
+            depth_images,
+            depth_views,
             framebuffers,
             extent,
+            present_mode,
             image_available_semaphores,
             render_finished_semaphores,
-            start_draw_fences,
+            frame_values: vec![0; image_count],
+            image_values: vec![0; image_count],
             image_count,
             current_image: 0
         }
     }
 
-    fn new_swapchain(instance: &ash::Instance,
-        device: &Device, 
+    //fifo is the only mode every surface has to support
+    fn pick_present_mode(device: &Device, window: &Window, requested: vk::PresentModeKHR) -> vk::PresentModeKHR {
+        if window.present_modes(device.physical).contains(&requested) {
+            requested
+        } else {
+            log::warn!("present mode {:?} is not supported, falling back to fifo", requested);
+            vk::PresentModeKHR::FIFO
+        }
+    }
+
+    //some platforms leave the extent to the swapchain, signalled by u32::MAX
+    fn pick_extent(window: &Window, capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
+        if capabilities.current_extent.width != u32::MAX {
+            return capabilities.current_extent;
+        }
+
+        let size = window.handle.inner_size();
+        vk::Extent2D {
+            width: size.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+            height: size.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height),
+        }
+    }
+
+    fn new_swapchain(device: &Device,
         window: &Window,
-        capabilities: &vk::SurfaceCapabilitiesKHR)
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+        extent: vk::Extent2D,
+        present_mode: vk::PresentModeKHR,
+        old_swapchain: vk::SwapchainKHR)
     -> (khr::Swapchain, vk::SwapchainKHR) {
 
         let queue_family_indices = [device.graphics_family.index];
         let info = vk::SwapchainCreateInfoKHR::builder()
-            .surface(window.surface)
+            .surface(window.surface.surface)
             .min_image_count(3.max(capabilities.min_image_count).min(capabilities.max_image_count))
             .image_format(window.format.format)
             .image_color_space(window.format.color_space)
-            .image_extent(capabilities.current_extent)
+            .image_extent(extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(&queue_family_indices)
             .pre_transform(capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::FIFO);
+            .present_mode(present_mode)
+            .old_swapchain(old_swapchain);
 
-        let loader = khr::Swapchain::new(instance, &device.logical);
+        let loader = khr::Swapchain::new(&device.instance.handle, &device.logical);
         let swapchain = unsafe {
             loader.create_swapchain(&info, None).unwrap()
         };
         (loader, swapchain)
     }
 
-    fn new_image_views(images: &Vec<vk::Image>, logical: &ash::Device) -> Vec<vk::ImageView> {
+    fn new_image_views(images: &Vec<vk::Image>, logical: &ash::Device, format: vk::Format) -> Vec<vk::ImageView> {
         let mut image_views = Vec::with_capacity(images.len());
         for image in images {
             let subresource_range = vk::ImageSubresourceRange::builder()
@@ -92,7 +175,7 @@ impl Swapchain {
             let info = vk::ImageViewCreateInfo::builder()
                 .image(*image)
                 .view_type(vk::ImageViewType::TYPE_2D)
-                .format(vk::Format::B8G8R8A8_UNORM)
+                .format(format)
                 .subresource_range(*subresource_range);
 
             image_views.push(unsafe {
@@ -103,10 +186,67 @@ impl Swapchain {
         image_views
     }
 
-    fn new_framebuffers(image_views: &Vec<vk::ImageView>, logical: &ash::Device, extent: vk::Extent2D, render_pass: vk::RenderPass) -> Vec<vk::Framebuffer> {
+    fn new_depth_image(device: &Device, extent: vk::Extent2D) -> (vk::Image, Allocation) {
+        let info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(device.depth_format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = unsafe {
+            device.logical.create_image(&info, None).unwrap()
+        };
+
+        let requirements = unsafe {
+            device.logical.get_image_memory_requirements(image)
+        };
+
+        let allocation = device.allocator.lock().unwrap().allocate(&AllocationCreateDesc {
+            name: "depth buffer",
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false
+        }).unwrap();
+
+        unsafe {
+            device.logical.bind_image_memory(image, allocation.memory(), allocation.offset()).unwrap();
+        }
+
+        (image, allocation)
+    }
+
+    fn new_depth_view(logical: &ash::Device, image: vk::Image, format: vk::Format) -> vk::ImageView {
+        let info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(sync::depth_range());
+
+        unsafe {
+            logical.create_image_view(&info, None).unwrap()
+        }
+    }
+
+    fn new_framebuffers(
+        image_views: &[vk::ImageView],
+        depth_views: &[vk::ImageView],
+        logical: &ash::Device,
+        extent: vk::Extent2D,
+        render_pass: vk::RenderPass)
+    -> Vec<vk::Framebuffer> {
         let mut framebuffers = Vec::with_capacity(image_views.len());
-        for &image_view in image_views {
-            let attachments = [image_view];
+        for (&image_view, &depth_view) in image_views.iter().zip(depth_views) {
+            let attachments = [image_view, depth_view];
             let info = vk::FramebufferCreateInfo::builder()
                 .render_pass(render_pass)
                 .attachments(&attachments)
@@ -122,16 +262,12 @@ impl Swapchain {
     }
 
     fn new_syncs(image_count: usize, logical: &ash::Device) -> 
-        (Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>) {
+        (Vec<vk::Semaphore>, Vec<vk::Semaphore>) {
         
         let semaphore_info = vk::SemaphoreCreateInfo::builder();
 
-        let fence_info = vk::FenceCreateInfo::builder()
-            .flags(vk::FenceCreateFlags::SIGNALED);
-
         let mut image_available_semaphores = Vec::with_capacity(image_count);
         let mut render_finished_semaphores = Vec::with_capacity(image_count);
-        let mut start_draw_fences = Vec::with_capacity(image_count);
 
         for _ in 0..image_count {
             image_available_semaphores.push(unsafe {
@@ -140,24 +276,37 @@ impl Swapchain {
             render_finished_semaphores.push(unsafe {
                 logical.create_semaphore(&semaphore_info, None).unwrap()
             });
-            start_draw_fences.push(unsafe {
-                logical.create_fence(&fence_info, None).unwrap()
-            });
         }
         (image_available_semaphores,
-        render_finished_semaphores,
-        start_draw_fences)
+        render_finished_semaphores)
     }
     
-    pub unsafe fn cleanup(&mut self, logical: &ash::Device) {
-        for i in 0..self.image_count {
-            logical.destroy_semaphore(self.image_available_semaphores[i], None);
-            logical.destroy_semaphore(self.render_finished_semaphores[i], None);
-            logical.destroy_fence(self.start_draw_fences[i], None);
-            
-            logical.destroy_framebuffer(self.framebuffers[i], None);
-            logical.destroy_image_view(self.image_views[i], None);
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        let logical = &self.device.logical;
+
+        unsafe {
+            for i in 0..self.image_count {
+                logical.destroy_semaphore(self.image_available_semaphores[i], None);
+                logical.destroy_semaphore(self.render_finished_semaphores[i], None);
+
+                logical.destroy_image_view(self.image_views[i], None);
+                logical.destroy_image_view(self.depth_views[i], None);
+            }
+            for (image, _) in &self.depth_images {
+                logical.destroy_image(*image, None);
+            }
+            for &framebuffer in &self.framebuffers {
+                logical.destroy_framebuffer(framebuffer, None);
+            }
+            self.loader.destroy_swapchain(self.swapchain, None);
         }
-        self.loader.destroy_swapchain(self.swapchain, None);
-    } 
+
+        let mut allocator = self.device.allocator.lock().unwrap();
+        for (_, allocation) in self.depth_images.drain(..) {
+            allocator.free(allocation).unwrap();
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,331 @@
+use super::Device;
+
+use ash::vk;
+use std::collections::HashMap;
+
+const WRITE_ACCESS: vk::AccessFlags2 = vk::AccessFlags2::from_raw(
+    vk::AccessFlags2::SHADER_WRITE.as_raw()
+        | vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw()
+        | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags2::HOST_WRITE.as_raw()
+        | vk::AccessFlags2::MEMORY_WRITE.as_raw());
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ResourceState {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+    pub layout: vk::ImageLayout
+}
+
+impl ResourceState {
+    pub const UNDEFINED: Self = Self::new(
+        vk::PipelineStageFlags2::NONE,
+        vk::AccessFlags2::NONE,
+        vk::ImageLayout::UNDEFINED);
+
+    //swapchain images wait on the acquire semaphore at color attachment output
+    pub const ACQUIRED: Self = Self::new(
+        vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        vk::AccessFlags2::NONE,
+        vk::ImageLayout::UNDEFINED);
+
+    pub const TRANSFER_SRC: Self = Self::new(
+        vk::PipelineStageFlags2::TRANSFER,
+        vk::AccessFlags2::TRANSFER_READ,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+
+    pub const TRANSFER_DST: Self = Self::new(
+        vk::PipelineStageFlags2::TRANSFER,
+        vk::AccessFlags2::TRANSFER_WRITE,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+
+    pub const COLOR_ATTACHMENT: Self = Self::new(
+        vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+    pub const DEPTH_ATTACHMENT: Self = Self::new(
+        vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS.as_raw()
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS.as_raw()),
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()),
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL);
+
+    pub const FRAGMENT_READ: Self = Self::new(
+        vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        vk::AccessFlags2::SHADER_READ,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+    pub const COMPUTE_READ: Self = Self::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::SHADER_READ,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+    pub const COMPUTE_WRITE: Self = Self::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::SHADER_READ.as_raw()
+                | vk::AccessFlags2::SHADER_WRITE.as_raw()),
+        vk::ImageLayout::GENERAL);
+
+    pub const VERTEX_INPUT: Self = Self::new(
+        vk::PipelineStageFlags2::VERTEX_INPUT,
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::VERTEX_ATTRIBUTE_READ.as_raw()
+                | vk::AccessFlags2::INDEX_READ.as_raw()),
+        vk::ImageLayout::UNDEFINED);
+
+    pub const INDIRECT: Self = Self::new(
+        vk::PipelineStageFlags2::DRAW_INDIRECT,
+        vk::AccessFlags2::INDIRECT_COMMAND_READ,
+        vk::ImageLayout::UNDEFINED);
+
+    pub const PRESENT: Self = Self::new(
+        vk::PipelineStageFlags2::NONE,
+        vk::AccessFlags2::NONE,
+        vk::ImageLayout::PRESENT_SRC_KHR);
+
+    pub const fn new(stage: vk::PipelineStageFlags2, access: vk::AccessFlags2, layout: vk::ImageLayout) -> Self {
+        Self {
+            stage,
+            access,
+            layout
+        }
+    }
+
+    fn writes(&self) -> bool {
+        self.access.intersects(WRITE_ACCESS)
+    }
+}
+
+struct TrackedImage {
+    state: ResourceState,
+    range: vk::ImageSubresourceRange
+}
+
+#[derive(Default)]
+pub struct ResourceTracker {
+    images: HashMap<vk::Image, TrackedImage>,
+    buffers: HashMap<vk::Buffer, ResourceState>,
+    image_barriers: Vec<vk::ImageMemoryBarrier2>,
+    buffer_barriers: Vec<vk::BufferMemoryBarrier2>
+}
+
+impl ResourceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_image(&mut self, image: vk::Image, range: vk::ImageSubresourceRange, state: ResourceState) {
+        self.images.insert(image, TrackedImage {
+            state,
+            range
+        });
+    }
+
+    pub fn register_buffer(&mut self, buffer: vk::Buffer, state: ResourceState) {
+        self.buffers.insert(buffer, state);
+    }
+
+    pub fn forget_image(&mut self, image: vk::Image) {
+        self.images.remove(&image);
+    }
+
+    pub fn forget_buffer(&mut self, buffer: vk::Buffer) {
+        self.buffers.remove(&buffer);
+    }
+
+    pub fn image_state(&self, image: vk::Image) -> Option<ResourceState> {
+        self.images.get(&image).map(|tracked| tracked.state)
+    }
+
+    pub fn buffer_state(&self, buffer: vk::Buffer) -> Option<ResourceState> {
+        self.buffers.get(&buffer).copied()
+    }
+
+    pub fn use_image(&mut self, image: vk::Image, state: ResourceState) {
+        //like buffers, images seen for the first time start out undefined, as color images with all their
+        //mips, anything else has to be registered
+        let tracked = self.images.entry(image).or_insert(TrackedImage {
+            state: ResourceState::UNDEFINED,
+            range: vk::ImageSubresourceRange {
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+                ..color_range(vk::REMAINING_MIP_LEVELS)
+            }
+        });
+
+        if let Some(old) = Self::transition(&mut tracked.state, state) {
+            self.image_barriers.push(vk::ImageMemoryBarrier2::builder()
+                .image(image)
+                .src_stage_mask(old.stage)
+                .src_access_mask(old.access)
+                .dst_stage_mask(state.stage)
+                .dst_access_mask(state.access)
+                .old_layout(old.layout)
+                .new_layout(state.layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(tracked.range)
+                .build());
+        }
+    }
+
+    pub fn use_buffer(&mut self, buffer: vk::Buffer, state: ResourceState) {
+        //buffers have no layout, only the stage and access matter
+        let state = ResourceState {
+            layout: vk::ImageLayout::UNDEFINED,
+            ..state
+        };
+        let current = self.buffers.entry(buffer).or_insert(ResourceState::UNDEFINED);
+
+        if let Some(old) = Self::transition(current, state) {
+            self.buffer_barriers.push(vk::BufferMemoryBarrier2::builder()
+                .buffer(buffer)
+                .src_stage_mask(old.stage)
+                .src_access_mask(old.access)
+                .dst_stage_mask(state.stage)
+                .dst_access_mask(state.access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build());
+        }
+    }
+
+    //returns the state to synchronize against, or None when the new use can overlap the previous one
+    fn transition(current: &mut ResourceState, next: ResourceState) -> Option<ResourceState> {
+        let layout_changes = next.layout != current.layout && next.layout != vk::ImageLayout::UNDEFINED;
+
+        if !layout_changes && !current.writes() && !next.writes() && current.stage != vk::PipelineStageFlags2::NONE {
+            //consecutive reads only widen the scope later writes have to wait on
+            current.stage |= next.stage;
+            current.access |= next.access;
+            return None;
+        }
+
+        let old = *current;
+        *current = next;
+        if !layout_changes {
+            current.layout = old.layout;
+        }
+
+        if old.stage == vk::PipelineStageFlags2::NONE && !layout_changes {
+            None
+        } else {
+            Some(old)
+        }
+    }
+
+    pub fn flush(&mut self, device: &Device, command_buffer: vk::CommandBuffer) {
+        if self.image_barriers.is_empty() && self.buffer_barriers.is_empty() {
+            return;
+        }
+
+        match &device.synchronization2 {
+            Some(synchronization2) => {
+                let dependency_info = vk::DependencyInfo::builder()
+                    .image_memory_barriers(&self.image_barriers)
+                    .buffer_memory_barriers(&self.buffer_barriers);
+
+                unsafe {
+                    synchronization2.cmd_pipeline_barrier2(command_buffer, &dependency_info);
+                }
+            },
+            None => self.flush_legacy(device, command_buffer),
+        }
+
+        self.image_barriers.clear();
+        self.buffer_barriers.clear();
+    }
+
+    //the low 32 bits of the synchronization2 flags match the original flags
+    fn flush_legacy(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let mut src_stage = vk::PipelineStageFlags::empty();
+        let mut dst_stage = vk::PipelineStageFlags::empty();
+
+        let image_barriers: Vec<_> = self.image_barriers.iter()
+            .map(|barrier| {
+                src_stage |= legacy_stage(barrier.src_stage_mask);
+                dst_stage |= legacy_stage(barrier.dst_stage_mask);
+
+                vk::ImageMemoryBarrier::builder()
+                    .image(barrier.image)
+                    .src_access_mask(legacy_access(barrier.src_access_mask))
+                    .dst_access_mask(legacy_access(barrier.dst_access_mask))
+                    .old_layout(barrier.old_layout)
+                    .new_layout(barrier.new_layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(barrier.subresource_range)
+                    .build()
+            })
+            .collect();
+
+        let buffer_barriers: Vec<_> = self.buffer_barriers.iter()
+            .map(|barrier| {
+                src_stage |= legacy_stage(barrier.src_stage_mask);
+                dst_stage |= legacy_stage(barrier.dst_stage_mask);
+
+                vk::BufferMemoryBarrier::builder()
+                    .buffer(barrier.buffer)
+                    .src_access_mask(legacy_access(barrier.src_access_mask))
+                    .dst_access_mask(legacy_access(barrier.dst_access_mask))
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .offset(barrier.offset)
+                    .size(barrier.size)
+                    .build()
+            })
+            .collect();
+
+        if src_stage.is_empty() {
+            src_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
+        }
+        if dst_stage.is_empty() {
+            dst_stage = vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+        }
+
+        unsafe {
+            device.logical.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_barriers,
+                &image_barriers);
+        }
+    }
+}
+
+fn legacy_stage(stage: vk::PipelineStageFlags2) -> vk::PipelineStageFlags {
+    vk::PipelineStageFlags::from_raw(stage.as_raw() as u32)
+}
+
+fn legacy_access(access: vk::AccessFlags2) -> vk::AccessFlags {
+    vk::AccessFlags::from_raw(access.as_raw() as u32)
+}
+
+pub fn color_range(level_count: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count,
+        base_array_layer: 0,
+        layer_count: 1
+    }
+}
+
+pub fn depth_range() -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::DEPTH,
+        ..color_range(1)
+    }
+}
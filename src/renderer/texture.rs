@@ -0,0 +1,1073 @@
+use super::{Buffer, Device};
+use super::sync::{self, ResourceTracker, ResourceState};
+
+use ash::vk;
+use anyhow::{Result, bail, anyhow};
+use basis_universal::{Transcoder, TranscoderTextureFormat, TranscodeParameters};
+use gpu_allocator::{MemoryLocation, vulkan::{Allocation, AllocationCreateDesc}};
+use std::{io::Read, path::Path, sync::Arc};
+
+const SAMPLED_FEATURES: vk::FormatFeatureFlags = vk::FormatFeatureFlags::from_raw(
+    vk::FormatFeatureFlags::SAMPLED_IMAGE.as_raw()
+        | vk::FormatFeatureFlags::TRANSFER_DST.as_raw());
+
+//ordered by preference when transcoding basis universal data
+const BASIS_TARGETS: [(TranscoderTextureFormat, vk::Format); 3] = [
+    (TranscoderTextureFormat::BC7_RGBA, vk::Format::BC7_UNORM_BLOCK),
+    (TranscoderTextureFormat::ASTC_4x4_RGBA, vk::Format::ASTC_4X4_UNORM_BLOCK),
+    (TranscoderTextureFormat::ETC2_RGBA, vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK),
+];
+
+//layout of the .basis files built from ktx2 basis lz and uastc payloads
+const BASIS_HEADER_SIZE: usize = 77;
+const BASIS_SLICE_SIZE: usize = 23;
+const BASIS_FLAG_ETC1S: u16 = 1;
+const BASIS_FLAG_ALPHA: u16 = 4;
+
+pub struct TextureData {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub levels: Vec<Vec<u8>>
+}
+
+impl TextureData {
+    pub fn from_ktx2(device: &Device, bytes: &[u8]) -> Result<Self> {
+        Self::parse_ktx2(bytes, &|format| device.supports_format(format, SAMPLED_FEATURES))
+    }
+
+    //supported tells which formats basis universal data can be transcoded to
+    fn parse_ktx2(bytes: &[u8], supported: &dyn Fn(vk::Format) -> bool) -> Result<Self> {
+        let reader = ktx2::Reader::new(bytes).map_err(|e| anyhow!("invalid ktx2 file: {:?}", e))?;
+        let header = reader.header();
+
+        let format = match header.format {
+            Some(format) => vk::Format::from_raw(format.value() as i32),
+            None => return Self::from_ktx2_universal(&reader, supported),
+        };
+        let extent = vk::Extent2D {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1)
+        };
+
+        let mut levels = Vec::with_capacity(reader.levels().len());
+        for (i, level) in reader.levels().enumerate() {
+            let data = ktx2_level_data(header.supercompression_scheme, &level)?;
+
+            //only the first layer and face are used
+            let size = level_size(format, mip_extent(extent, i as u32))?;
+            if data.len() < size {
+                bail!("ktx2 level {} is truncated", i);
+            }
+            levels.push(data[..size].to_vec());
+        }
+
+        Ok(Self {
+            format,
+            extent,
+            levels
+        })
+    }
+
+    //basis lz and uastc payloads are repacked as a .basis file for the transcoder
+    fn from_ktx2_universal(reader: &ktx2::Reader<&[u8]>, supported: &dyn Fn(vk::Format) -> bool) -> Result<Self> {
+        let header = reader.header();
+        let dfd = reader.basic_dfd().ok_or_else(|| anyhow!("ktx2 file has no data format descriptor"))?;
+        let extent = vk::Extent2D {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1)
+        };
+
+        //images of a level, of which only the first layer and face are used
+        let images = |level: u32| header.layer_count.max(1) * header.face_count * (header.pixel_depth >> level).max(1);
+
+        let mut slices = vec![];
+        let file = match (header.supercompression_scheme, dfd.color_model) {
+            (Some(ktx2::SupercompressionScheme::BasisLZ), _) => {
+                let global = reader.supercompression_global_data();
+                let lengths = [read_u32(global, 4)?, read_u32(global, 8)?, read_u32(global, 12)?];
+
+                //the image descriptions are followed by the endpoints, selectors and tables
+                let image_count: u32 = (0..reader.levels().len() as u32).map(images).sum();
+                let codebooks_offset = 20 + 20 * image_count as usize;
+                let codebooks_length = lengths.iter().sum::<u32>() as usize;
+                let codebooks = BasisCodebooks {
+                    endpoint_count: read_u16(global, 0)?,
+                    selector_count: read_u16(global, 2)?,
+                    lengths,
+                    data: global.get(codebooks_offset..codebooks_offset + codebooks_length)
+                        .ok_or_else(|| anyhow!("ktx2 basis lz data is truncated"))?
+                };
+
+                let mut first_image = 0;
+                for (i, level) in reader.levels().enumerate() {
+                    let i = i as u32;
+                    let desc = 20 + 20 * first_image as usize;
+                    for (alpha, offset) in [(false, desc + 4), (true, desc + 12)] {
+                        let (start, length) = (read_u32(global, offset)? as usize, read_u32(global, offset + 4)? as usize);
+                        if alpha && length == 0 {
+                            continue;
+                        }
+
+                        let data = level.data.get(start..start + length)
+                            .ok_or_else(|| anyhow!("ktx2 level {} is truncated", i))?;
+                        slices.push(BasisSlice {
+                            extent: mip_extent(extent, i),
+                            level: i,
+                            alpha,
+                            data: data.to_vec()
+                        });
+                    }
+                    first_image += images(i);
+                }
+
+                let alpha = slices.iter().any(|slice| slice.alpha);
+                basis_file(BASIS_FLAG_ETC1S | if alpha { BASIS_FLAG_ALPHA } else { 0 }, Some(&codebooks), &slices)
+            },
+            (None | Some(ktx2::SupercompressionScheme::Zstandard), Some(ktx2::ColorModel::UASTC)) => {
+                for (i, level) in reader.levels().enumerate() {
+                    let i = i as u32;
+                    let data = ktx2_level_data(header.supercompression_scheme, &level)?;
+
+                    //uastc blocks are 4x4 and 16 bytes, like astc 4x4
+                    let size = level_size(vk::Format::ASTC_4X4_UNORM_BLOCK, mip_extent(extent, i))?;
+                    if data.len() < size {
+                        bail!("ktx2 level {} is truncated", i);
+                    }
+                    slices.push(BasisSlice {
+                        extent: mip_extent(extent, i),
+                        level: i,
+                        alpha: false,
+                        data: data[..size].to_vec()
+                    });
+                }
+
+                //rgba and rrrg channels carry alpha
+                let alpha = dfd.sample_information.first().is_some_and(|sample| matches!(sample.channel_type, 3 | 5));
+                basis_file(if alpha { BASIS_FLAG_ALPHA } else { 0 }, None, &slices)
+            },
+            (scheme, model) => bail!("unsupported ktx2 universal payload {:?} with {:?}", model, scheme),
+        };
+
+        let mut data = Self::parse_basis(&file, supported)?;
+        if reader.transfer_function() == Some(ktx2::TransferFunction::SRGB) {
+            data.format = srgb_format(data.format);
+        }
+
+        Ok(data)
+    }
+
+    pub fn from_dds(bytes: &[u8]) -> Result<Self> {
+        let dds = ddsfile::Dds::read(bytes)?;
+
+        //ddsfile reads legacy dxt files as srgb dxgi formats, their d3d format keeps them linear
+        let format = dds.get_d3d_format().and_then(d3d_format)
+            .or_else(|| dds.get_dxgi_format().and_then(dxgi_format))
+            .ok_or_else(|| anyhow!("unsupported dds format"))?;
+
+        let extent = vk::Extent2D {
+            width: dds.get_width(),
+            height: dds.get_height()
+        };
+
+        let data = dds.get_data(0)?;
+        let mut offset = 0;
+        let mut levels = Vec::with_capacity(dds.get_num_mipmap_levels() as usize);
+        for i in 0..dds.get_num_mipmap_levels() {
+            let size = level_size(format, mip_extent(extent, i))?;
+            if data.len() < offset + size {
+                bail!("dds level {} is truncated", i);
+            }
+            levels.push(data[offset..offset + size].to_vec());
+            offset += size;
+        }
+
+        Ok(Self {
+            format,
+            extent,
+            levels
+        })
+    }
+
+    pub fn from_basis(device: &Device, bytes: &[u8]) -> Result<Self> {
+        Self::parse_basis(bytes, &|format| device.supports_format(format, SAMPLED_FEATURES))
+    }
+
+    fn parse_basis(bytes: &[u8], supported: &dyn Fn(vk::Format) -> bool) -> Result<Self> {
+        let mut transcoder = Transcoder::new();
+        transcoder.prepare_transcoding(bytes).map_err(|_| anyhow!("invalid basis file"))?;
+
+        let (target, format) = BASIS_TARGETS.iter()
+            .copied()
+            .find(|&(_, format)| supported(format))
+            .unwrap_or((TranscoderTextureFormat::RGBA32, vk::Format::R8G8B8A8_UNORM));
+
+        let description = transcoder.image_level_description(bytes, 0, 0)
+            .ok_or_else(|| anyhow!("basis file has no images"))?;
+        let extent = vk::Extent2D {
+            width: description.original_width,
+            height: description.original_height
+        };
+
+        let level_count = transcoder.image_level_count(bytes, 0);
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for level_index in 0..level_count {
+            let params = TranscodeParameters {
+                image_index: 0,
+                level_index,
+                ..Default::default()
+            };
+            levels.push(transcoder.transcode_image_level(bytes, target, params)
+                .map_err(|e| anyhow!("failed to transcode basis level {}: {:?}", level_index, e))?);
+        }
+        transcoder.end_transcoding();
+
+        Ok(Self {
+            format,
+            extent,
+            levels
+        })
+    }
+
+    //block compressed data in formats that can't be sampled is decoded on the cpu
+    pub fn or_decoded(self, supported: impl Fn(vk::Format) -> bool) -> Result<Self> {
+        if supported(self.format) {
+            Ok(self)
+        } else {
+            self.decode_rgba8()
+        }
+    }
+
+    pub fn decode_rgba8(&self) -> Result<Self> {
+        let format = if is_srgb(self.format) {
+            vk::Format::B8G8R8A8_SRGB
+        } else {
+            vk::Format::B8G8R8A8_UNORM
+        };
+
+        let mut levels = Vec::with_capacity(self.levels.len());
+        for (i, level) in self.levels.iter().enumerate() {
+            let extent = mip_extent(self.extent, i as u32);
+            let (width, height) = (extent.width as usize, extent.height as usize);
+
+            //decoded pixels are packed as little endian bgra
+            let mut pixels = vec![0u32; width * height];
+            decode_blocks(self.format, level, width, height, &mut pixels)?;
+
+            levels.push(pixels.iter().flat_map(|p| p.to_le_bytes()).collect());
+        }
+
+        Ok(Self {
+            format,
+            extent: self.extent,
+            levels
+        })
+    }
+}
+
+//filtering and wrapping of a texture's sampler, linear and repeating by default
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT
+        }
+    }
+}
+
+pub struct Texture {
+    pub device: Arc<Device>,
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub allocation: Allocation,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32
+}
+
+impl Texture {
+    pub fn load(device: &Arc<Device>, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+
+        let data = match path.extension().and_then(|e| e.to_str()) {
+            Some("ktx2") => TextureData::from_ktx2(device, &bytes)?,
+            Some("dds") => TextureData::from_dds(&bytes)?,
+            Some("basis") => TextureData::from_basis(device, &bytes)?,
+            _ => bail!("unknown texture container {:?}", path),
+        };
+
+        let texture = Self::new(device, data)?;
+        texture.set_name(&path.to_string_lossy());
+
+        Ok(texture)
+    }
+
+    pub fn new(device: &Arc<Device>, data: TextureData) -> Result<Self> {
+        Self::with_sampler(device, data, SamplerDesc::default())
+    }
+
+    pub fn with_sampler(device: &Arc<Device>, data: TextureData, sampler: SamplerDesc) -> Result<Self> {
+        let data = data.or_decoded(|format| device.supports_format(format, SAMPLED_FEATURES))?;
+
+        let mip_levels = data.levels.len() as u32;
+        let (image, allocation) = Self::new_image(device, &data);
+        Self::upload(device, image, &data);
+
+        let view = Self::new_view(device, image, data.format, mip_levels);
+        let sampler = Self::new_sampler(device, mip_levels, &sampler);
+
+        Ok(Self {
+            device: device.clone(),
+            image,
+            view,
+            sampler,
+            allocation,
+            format: data.format,
+            extent: data.extent,
+            mip_levels
+        })
+    }
+
+    pub fn set_name(&self, name: &str) {
+        self.device.set_object_name(self.image, name);
+        self.device.set_object_name(self.view, &format!("{} view", name));
+        self.device.set_object_name(self.sampler, &format!("{} sampler", name));
+    }
+
+    fn new_image(device: &Device, data: &TextureData) -> (vk::Image, Allocation) {
+        let info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(data.format)
+            .extent(vk::Extent3D {
+                width: data.extent.width,
+                height: data.extent.height,
+                depth: 1
+            })
+            .mip_levels(data.levels.len() as u32)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = unsafe {
+            device.logical.create_image(&info, None).unwrap()
+        };
+
+        let requirements = unsafe {
+            device.logical.get_image_memory_requirements(image)
+        };
+
+        let allocation = device.allocator.lock().unwrap().allocate(&AllocationCreateDesc {
+            name: "texture",
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false
+        }).unwrap();
+
+        unsafe {
+            device.logical.bind_image_memory(image, allocation.memory(), allocation.offset()).unwrap();
+        }
+
+        (image, allocation)
+    }
+
+    fn upload(device: &Arc<Device>, image: vk::Image, data: &TextureData) {
+        let staging = Buffer::new_staging(device, &data.levels.concat());
+
+        let mut regions = Vec::with_capacity(data.levels.len());
+        let mut offset = 0;
+        for (i, level) in data.levels.iter().enumerate() {
+            let extent = mip_extent(data.extent, i as u32);
+            regions.push(vk::BufferImageCopy {
+                buffer_offset: offset,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: i as u32,
+                    base_array_layer: 0,
+                    layer_count: 1
+                },
+                image_extent: vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1
+                },
+                ..Default::default()
+            });
+            offset += level.len() as vk::DeviceSize;
+        }
+
+        let mut tracker = ResourceTracker::new();
+        tracker.register_image(image, sync::color_range(data.levels.len() as u32), ResourceState::UNDEFINED);
+
+        device.submit_once(|command_buffer| {
+            tracker.use_image(image, ResourceState::TRANSFER_DST);
+            tracker.flush(device, command_buffer);
+
+            unsafe {
+                device.logical.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging.buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions);
+            }
+
+            tracker.use_image(image, ResourceState::FRAGMENT_READ);
+            tracker.flush(device, command_buffer);
+        });
+    }
+
+    fn new_view(device: &Device, image: vk::Image, format: vk::Format, mip_levels: u32) -> vk::ImageView {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(mip_levels)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(*subresource_range);
+
+        unsafe {
+            device.logical.create_image_view(&info, None).unwrap()
+        }
+    }
+
+    fn new_sampler(device: &Device, mip_levels: u32, desc: &SamplerDesc) -> vk::Sampler {
+        let info = vk::SamplerCreateInfo::builder()
+            .mag_filter(desc.mag_filter)
+            .min_filter(desc.min_filter)
+            .mipmap_mode(desc.mipmap_mode)
+            .address_mode_u(desc.address_mode_u)
+            .address_mode_v(desc.address_mode_v)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .max_lod(mip_levels as f32);
+
+        unsafe {
+            device.logical.create_sampler(&info, None).unwrap()
+        }
+    }
+
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.logical.destroy_sampler(self.sampler, None);
+            self.device.logical.destroy_image_view(self.view, None);
+            self.device.logical.destroy_image(self.image, None);
+        }
+        self.device.allocator.lock().unwrap().free(std::mem::take(&mut self.allocation)).unwrap();
+    }
+}
+
+fn mip_extent(extent: vk::Extent2D, level: u32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: (extent.width >> level).max(1),
+        height: (extent.height >> level).max(1)
+    }
+}
+
+fn level_size(format: vk::Format, extent: vk::Extent2D) -> Result<usize> {
+    let (block_width, block_height, block_bytes) = block_info(format)
+        .ok_or_else(|| anyhow!("unsupported texture format {:?}", format))?;
+
+    let blocks_x = extent.width.div_ceil(block_width);
+    let blocks_y = extent.height.div_ceil(block_height);
+
+    Ok((blocks_x * blocks_y * block_bytes) as usize)
+}
+
+fn block_info(format: vk::Format) -> Option<(u32, u32, u32)> {
+    use vk::Format as F;
+
+    let info = match format {
+        F::R8G8B8A8_UNORM | F::R8G8B8A8_SRGB
+        | F::B8G8R8A8_UNORM | F::B8G8R8A8_SRGB => (1, 1, 4),
+
+        F::BC1_RGB_UNORM_BLOCK | F::BC1_RGB_SRGB_BLOCK
+        | F::BC1_RGBA_UNORM_BLOCK | F::BC1_RGBA_SRGB_BLOCK
+        | F::BC4_UNORM_BLOCK | F::BC4_SNORM_BLOCK
+        | F::ETC2_R8G8B8_UNORM_BLOCK | F::ETC2_R8G8B8_SRGB_BLOCK
+        | F::ETC2_R8G8B8A1_UNORM_BLOCK | F::ETC2_R8G8B8A1_SRGB_BLOCK
+        | F::EAC_R11_UNORM_BLOCK | F::EAC_R11_SNORM_BLOCK => (4, 4, 8),
+
+        F::BC2_UNORM_BLOCK | F::BC2_SRGB_BLOCK
+        | F::BC3_UNORM_BLOCK | F::BC3_SRGB_BLOCK
+        | F::BC5_UNORM_BLOCK | F::BC5_SNORM_BLOCK
+        | F::BC6H_UFLOAT_BLOCK | F::BC6H_SFLOAT_BLOCK
+        | F::BC7_UNORM_BLOCK | F::BC7_SRGB_BLOCK
+        | F::ETC2_R8G8B8A8_UNORM_BLOCK | F::ETC2_R8G8B8A8_SRGB_BLOCK
+        | F::EAC_R11G11_UNORM_BLOCK | F::EAC_R11G11_SNORM_BLOCK => (4, 4, 16),
+
+        _ => {
+            let (width, height) = astc_block(format)?;
+            (width, height, 16)
+        }
+    };
+
+    Some(info)
+}
+
+fn astc_block(format: vk::Format) -> Option<(u32, u32)> {
+    use vk::Format as F;
+
+    let block = match format {
+        F::ASTC_4X4_UNORM_BLOCK | F::ASTC_4X4_SRGB_BLOCK => (4, 4),
+        F::ASTC_5X4_UNORM_BLOCK | F::ASTC_5X4_SRGB_BLOCK => (5, 4),
+        F::ASTC_5X5_UNORM_BLOCK | F::ASTC_5X5_SRGB_BLOCK => (5, 5),
+        F::ASTC_6X5_UNORM_BLOCK | F::ASTC_6X5_SRGB_BLOCK => (6, 5),
+        F::ASTC_6X6_UNORM_BLOCK | F::ASTC_6X6_SRGB_BLOCK => (6, 6),
+        F::ASTC_8X5_UNORM_BLOCK | F::ASTC_8X5_SRGB_BLOCK => (8, 5),
+        F::ASTC_8X6_UNORM_BLOCK | F::ASTC_8X6_SRGB_BLOCK => (8, 6),
+        F::ASTC_8X8_UNORM_BLOCK | F::ASTC_8X8_SRGB_BLOCK => (8, 8),
+        F::ASTC_10X5_UNORM_BLOCK | F::ASTC_10X5_SRGB_BLOCK => (10, 5),
+        F::ASTC_10X6_UNORM_BLOCK | F::ASTC_10X6_SRGB_BLOCK => (10, 6),
+        F::ASTC_10X8_UNORM_BLOCK | F::ASTC_10X8_SRGB_BLOCK => (10, 8),
+        F::ASTC_10X10_UNORM_BLOCK | F::ASTC_10X10_SRGB_BLOCK => (10, 10),
+        F::ASTC_12X10_UNORM_BLOCK | F::ASTC_12X10_SRGB_BLOCK => (12, 10),
+        F::ASTC_12X12_UNORM_BLOCK | F::ASTC_12X12_SRGB_BLOCK => (12, 12),
+        _ => return None,
+    };
+
+    Some(block)
+}
+
+fn is_srgb(format: vk::Format) -> bool {
+    use vk::Format as F;
+
+    matches!(format,
+        F::R8_SRGB | F::R8G8_SRGB | F::R8G8B8_SRGB | F::B8G8R8_SRGB
+        | F::R8G8B8A8_SRGB | F::B8G8R8A8_SRGB | F::A8B8G8R8_SRGB_PACK32
+        | F::BC1_RGB_SRGB_BLOCK | F::BC1_RGBA_SRGB_BLOCK
+        | F::BC2_SRGB_BLOCK | F::BC3_SRGB_BLOCK | F::BC7_SRGB_BLOCK
+        | F::ETC2_R8G8B8_SRGB_BLOCK | F::ETC2_R8G8B8A1_SRGB_BLOCK | F::ETC2_R8G8B8A8_SRGB_BLOCK
+        | F::ASTC_4X4_SRGB_BLOCK | F::ASTC_5X4_SRGB_BLOCK | F::ASTC_5X5_SRGB_BLOCK
+        | F::ASTC_6X5_SRGB_BLOCK | F::ASTC_6X6_SRGB_BLOCK | F::ASTC_8X5_SRGB_BLOCK
+        | F::ASTC_8X6_SRGB_BLOCK | F::ASTC_8X8_SRGB_BLOCK | F::ASTC_10X5_SRGB_BLOCK
+        | F::ASTC_10X6_SRGB_BLOCK | F::ASTC_10X8_SRGB_BLOCK | F::ASTC_10X10_SRGB_BLOCK
+        | F::ASTC_12X10_SRGB_BLOCK | F::ASTC_12X12_SRGB_BLOCK)
+}
+
+//the srgb variant of a basis transcoding target
+fn srgb_format(format: vk::Format) -> vk::Format {
+    use vk::Format as F;
+
+    match format {
+        F::BC7_UNORM_BLOCK => F::BC7_SRGB_BLOCK,
+        F::ASTC_4X4_UNORM_BLOCK => F::ASTC_4X4_SRGB_BLOCK,
+        F::ETC2_R8G8B8A8_UNORM_BLOCK => F::ETC2_R8G8B8A8_SRGB_BLOCK,
+        F::R8G8B8A8_UNORM => F::R8G8B8A8_SRGB,
+        _ => format,
+    }
+}
+
+fn ktx2_level_data(scheme: Option<ktx2::SupercompressionScheme>, level: &ktx2::Level) -> Result<Vec<u8>> {
+    let data = match scheme {
+        None => level.data.to_vec(),
+        Some(ktx2::SupercompressionScheme::Zstandard) => {
+            let mut decoder = ruzstd::decoding::StreamingDecoder::new(level.data)
+                .map_err(|e| anyhow!("invalid zstd stream: {:?}", e))?;
+            let mut data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+            decoder.read_to_end(&mut data)?;
+            data
+        },
+        Some(scheme) => bail!("unsupported ktx2 supercompression {:?}", scheme),
+    };
+
+    Ok(data)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).ok_or_else(|| anyhow!("ktx2 basis lz data is truncated"))?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or_else(|| anyhow!("ktx2 basis lz data is truncated"))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+//the etc1s codebooks of a basis lz payload, endpoints, selectors and tables back to back
+struct BasisCodebooks<'a> {
+    endpoint_count: u16,
+    selector_count: u16,
+    lengths: [u32; 3],
+    data: &'a [u8]
+}
+
+//slices of the first image, an alpha slice follows the rgb slice of its level
+struct BasisSlice {
+    extent: vk::Extent2D,
+    level: u32,
+    alpha: bool,
+    data: Vec<u8>
+}
+
+//a .basis file with a single image, laid out as
+//header | slice descriptions | codebooks | slices
+fn basis_file(flags: u16, codebooks: Option<&BasisCodebooks>, slices: &[BasisSlice]) -> Vec<u8> {
+    fn put(file: &mut Vec<u8>, value: usize, bytes: usize) {
+        file.extend_from_slice(&(value as u64).to_le_bytes()[..bytes]);
+    }
+
+    let codebooks_offset = BASIS_HEADER_SIZE + slices.len() * BASIS_SLICE_SIZE;
+    let (endpoint_count, selector_count, lengths, codebook_data) = codebooks
+        .map_or((0, 0, [0; 3], &[][..]), |c| (c.endpoint_count, c.selector_count, c.lengths.map(|l| l as usize), c.data));
+    let slices_offset = codebooks_offset + codebook_data.len();
+    let size = slices_offset + slices.iter().map(|slice| slice.data.len()).sum::<usize>();
+
+    let mut file = Vec::with_capacity(size);
+    put(&mut file, 0x4273, 2); //signature
+    put(&mut file, 0x13, 2); //version
+    put(&mut file, BASIS_HEADER_SIZE, 2);
+    put(&mut file, 0, 2); //header crc, not checked by the transcoder
+    put(&mut file, size - BASIS_HEADER_SIZE, 4);
+    put(&mut file, 0, 2); //data crc
+    put(&mut file, slices.len(), 3);
+    put(&mut file, 1, 3); //images
+    put(&mut file, if flags & BASIS_FLAG_ETC1S != 0 { 0 } else { 1 }, 1); //etc1s or uastc 4x4
+    put(&mut file, flags as usize, 2);
+    put(&mut file, 0, 1); //2d texture
+    put(&mut file, 0, 3); //frame time
+    put(&mut file, 0, 4); //reserved
+    put(&mut file, 0, 4); //user data
+    put(&mut file, 0, 4);
+    put(&mut file, endpoint_count as usize, 2);
+    put(&mut file, codebooks_offset, 4);
+    put(&mut file, lengths[0], 3);
+    put(&mut file, selector_count as usize, 2);
+    put(&mut file, codebooks_offset + lengths[0], 4);
+    put(&mut file, lengths[1], 3);
+    put(&mut file, codebooks_offset + lengths[0] + lengths[1], 4);
+    put(&mut file, lengths[2], 4);
+    put(&mut file, BASIS_HEADER_SIZE, 4); //slice descriptions
+    put(&mut file, 0, 4); //extended data
+    put(&mut file, 0, 4);
+
+    let mut offset = slices_offset;
+    for slice in slices {
+        put(&mut file, 0, 3); //image
+        put(&mut file, slice.level as usize, 1);
+        put(&mut file, slice.alpha as usize, 1);
+        put(&mut file, slice.extent.width as usize, 2);
+        put(&mut file, slice.extent.height as usize, 2);
+        put(&mut file, slice.extent.width.div_ceil(4) as usize, 2);
+        put(&mut file, slice.extent.height.div_ceil(4) as usize, 2);
+        put(&mut file, offset, 4);
+        put(&mut file, slice.data.len(), 4);
+        put(&mut file, 0, 2); //slice crc
+        offset += slice.data.len();
+    }
+
+    file.extend_from_slice(codebook_data);
+    for slice in slices {
+        file.extend_from_slice(&slice.data);
+    }
+
+    file
+}
+
+fn decode_blocks(format: vk::Format, data: &[u8], width: usize, height: usize, pixels: &mut [u32]) -> Result<()> {
+    use vk::Format as F;
+    use texture2ddecoder as t2d;
+
+    let result = match format {
+        F::BC1_RGB_UNORM_BLOCK | F::BC1_RGB_SRGB_BLOCK => t2d::decode_bc1(data, width, height, pixels),
+        F::BC1_RGBA_UNORM_BLOCK | F::BC1_RGBA_SRGB_BLOCK => t2d::decode_bc1a(data, width, height, pixels),
+        F::BC2_UNORM_BLOCK | F::BC2_SRGB_BLOCK => t2d::decode_bc2(data, width, height, pixels),
+        F::BC3_UNORM_BLOCK | F::BC3_SRGB_BLOCK => t2d::decode_bc3(data, width, height, pixels),
+        F::BC4_UNORM_BLOCK => t2d::decode_bc4(data, width, height, pixels),
+        F::BC5_UNORM_BLOCK => t2d::decode_bc5(data, width, height, pixels),
+        F::BC7_UNORM_BLOCK | F::BC7_SRGB_BLOCK => t2d::decode_bc7(data, width, height, pixels),
+        F::ETC2_R8G8B8_UNORM_BLOCK | F::ETC2_R8G8B8_SRGB_BLOCK => t2d::decode_etc2_rgb(data, width, height, pixels),
+        F::ETC2_R8G8B8A1_UNORM_BLOCK | F::ETC2_R8G8B8A1_SRGB_BLOCK => t2d::decode_etc2_rgba1(data, width, height, pixels),
+        F::ETC2_R8G8B8A8_UNORM_BLOCK | F::ETC2_R8G8B8A8_SRGB_BLOCK => t2d::decode_etc2_rgba8(data, width, height, pixels),
+        F::EAC_R11_UNORM_BLOCK => t2d::decode_eacr(data, width, height, pixels),
+        F::EAC_R11G11_UNORM_BLOCK => t2d::decode_eacrg(data, width, height, pixels),
+        _ => match astc_block(format) {
+            Some((block_width, block_height)) => t2d::decode_astc(
+                data,
+                width,
+                height,
+                block_width as usize,
+                block_height as usize,
+                pixels),
+            None => bail!("no cpu fallback for texture format {:?}", format),
+        },
+    };
+
+    result.map_err(|e| anyhow!("failed to decode {:?}: {}", format, e))
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Option<vk::Format> {
+    use ddsfile::DxgiFormat as D;
+    use vk::Format as F;
+
+    let format = match format {
+        D::R8G8B8A8_UNorm => F::R8G8B8A8_UNORM,
+        D::R8G8B8A8_UNorm_sRGB => F::R8G8B8A8_SRGB,
+        D::B8G8R8A8_UNorm => F::B8G8R8A8_UNORM,
+        D::B8G8R8A8_UNorm_sRGB => F::B8G8R8A8_SRGB,
+        D::BC1_UNorm => F::BC1_RGBA_UNORM_BLOCK,
+        D::BC1_UNorm_sRGB => F::BC1_RGBA_SRGB_BLOCK,
+        D::BC2_UNorm => F::BC2_UNORM_BLOCK,
+        D::BC2_UNorm_sRGB => F::BC2_SRGB_BLOCK,
+        D::BC3_UNorm => F::BC3_UNORM_BLOCK,
+        D::BC3_UNorm_sRGB => F::BC3_SRGB_BLOCK,
+        D::BC4_UNorm => F::BC4_UNORM_BLOCK,
+        D::BC4_SNorm => F::BC4_SNORM_BLOCK,
+        D::BC5_UNorm => F::BC5_UNORM_BLOCK,
+        D::BC5_SNorm => F::BC5_SNORM_BLOCK,
+        D::BC6H_UF16 => F::BC6H_UFLOAT_BLOCK,
+        D::BC6H_SF16 => F::BC6H_SFLOAT_BLOCK,
+        D::BC7_UNorm => F::BC7_UNORM_BLOCK,
+        D::BC7_UNorm_sRGB => F::BC7_SRGB_BLOCK,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+fn d3d_format(format: ddsfile::D3DFormat) -> Option<vk::Format> {
+    use ddsfile::D3DFormat as D;
+    use vk::Format as F;
+
+    let format = match format {
+        D::A8B8G8R8 => F::R8G8B8A8_UNORM,
+        D::A8R8G8B8 => F::B8G8R8A8_UNORM,
+        D::DXT1 => F::BC1_RGBA_UNORM_BLOCK,
+        D::DXT2 | D::DXT3 => F::BC2_UNORM_BLOCK,
+        D::DXT4 | D::DXT5 => F::BC3_UNORM_BLOCK,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use basis_universal::{BasisTextureFormat, Compressor, CompressorParams};
+
+    const RGBA8: u32 = 37;
+    const UASTC: u8 = 166;
+    const LINEAR: u8 = 1;
+    const SRGB: u8 = 2;
+
+    fn put(file: &mut Vec<u8>, value: usize, bytes: usize) {
+        file.extend_from_slice(&(value as u64).to_le_bytes()[..bytes]);
+    }
+
+    fn get(data: &[u8], offset: usize, bytes: usize) -> usize {
+        let mut value = [0; 8];
+        value[..bytes].copy_from_slice(&data[offset..offset + bytes]);
+        u64::from_le_bytes(value) as usize
+    }
+
+    //a 2d ktx2 file with one basic data format descriptor block without samples
+    fn ktx2_file(format: u32, scheme: u32, color_model: u8, transfer: u8, extent: (u32, u32), levels: &[Vec<u8>]) -> Vec<u8> {
+        let dfd_offset = 80 + 24 * levels.len();
+        let dfd_length = 4 + 8 + 16;
+
+        let mut file = vec![0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+        for value in [format, 1, extent.0, extent.1, 0, 0, 1, levels.len() as u32, scheme] {
+            put(&mut file, value as usize, 4);
+        }
+        put(&mut file, dfd_offset, 4);
+        put(&mut file, dfd_length, 4);
+        put(&mut file, 0, 4); //key value data
+        put(&mut file, 0, 4);
+        put(&mut file, 0, 8); //supercompression global data
+        put(&mut file, 0, 8);
+
+        let mut offset = dfd_offset + dfd_length;
+        for level in levels {
+            put(&mut file, offset, 8);
+            put(&mut file, level.len(), 8);
+            put(&mut file, level.len(), 8);
+            offset += level.len();
+        }
+
+        put(&mut file, dfd_length, 4);
+        put(&mut file, 0, 4); //khronos basic block
+        put(&mut file, 2, 2);
+        put(&mut file, 24, 2);
+        file.extend_from_slice(&[color_model, 1, transfer, 0]);
+        file.extend_from_slice(&[0; 12]);
+
+        for level in levels {
+            file.extend_from_slice(level);
+        }
+
+        file
+    }
+
+    //a single raw block zstd frame
+    fn zstd_frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd, 0x20, data.len() as u8];
+        put(&mut frame, data.len() << 3 | 1, 3);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn compress(format: BasisTextureFormat) -> Vec<u8> {
+        let pixels: Vec<u8> = (0..64u8).flat_map(|i| [i * 4, 255 - i * 4, (i % 8) * 32, 255]).collect();
+
+        let mut params = CompressorParams::new();
+        params.set_basis_format(format);
+        params.set_generate_mipmaps(true);
+        params.set_print_status_to_stdout(false);
+        params.source_image_mut(0).init(&pixels, 8, 8, 4);
+
+        let mut compressor = Compressor::new(1);
+        unsafe {
+            assert!(compressor.init(&params));
+            compressor.process().unwrap();
+        }
+        compressor.basis_file().to_vec()
+    }
+
+    //the slices of an encoded .basis file, read back from its slice descriptions
+    fn basis_slices(file: &[u8]) -> Vec<BasisSlice> {
+        let descriptions = get(file, 65, 4);
+        (0..get(file, 14, 3))
+            .map(|i| {
+                let desc = descriptions + i * BASIS_SLICE_SIZE;
+                let (offset, size) = (get(file, desc + 13, 4), get(file, desc + 17, 4));
+                BasisSlice {
+                    extent: vk::Extent2D {
+                        width: get(file, desc + 5, 2) as u32,
+                        height: get(file, desc + 7, 2) as u32
+                    },
+                    level: get(file, desc + 3, 1) as u32,
+                    alpha: get(file, desc + 4, 1) & 1 != 0,
+                    data: file[offset..offset + size].to_vec()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ktx2_levels_are_cut_to_their_mip_size() {
+        //the first level carries a second layer, which is dropped
+        let levels = vec![(0..64).collect(), vec![1; 8], vec![2; 4]];
+        let file = ktx2_file(RGBA8, 0, 1, LINEAR, (4, 2), &levels);
+
+        let data = TextureData::parse_ktx2(&file, &|_| true).unwrap();
+        assert_eq!(data.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(data.extent, vk::Extent2D { width: 4, height: 2 });
+        assert_eq!(data.levels, vec![(0..32).collect(), vec![1; 8], vec![2; 4]]);
+
+        let truncated = ktx2_file(RGBA8, 0, 1, LINEAR, (4, 2), &[vec![0; 31]]);
+        assert!(TextureData::parse_ktx2(&truncated, &|_| true).is_err());
+        assert!(TextureData::parse_ktx2(&file[..file.len() - 1], &|_| true).is_err());
+    }
+
+    #[test]
+    fn zstd_ktx2_levels_are_decompressed() {
+        let level: Vec<u8> = (0..64).collect();
+        let file = ktx2_file(RGBA8, 2, 1, LINEAR, (4, 4), &[zstd_frame(&level)]);
+
+        let data = TextureData::parse_ktx2(&file, &|_| true).unwrap();
+        assert_eq!(data.levels, vec![level]);
+    }
+
+    #[test]
+    fn dds_mips_are_split_by_level_size() {
+        let mut dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height: 8,
+            width: 8,
+            depth: None,
+            format: ddsfile::DxgiFormat::BC1_UNorm_sRGB,
+            mipmap_levels: Some(4),
+            array_layers: None,
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+            alpha_mode: ddsfile::AlphaMode::Unknown
+        }).unwrap();
+        for (i, byte) in dds.data.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut file = vec![];
+        dds.write(&mut file).unwrap();
+
+        //8x8, 4x4, 2x2 and 1x1 each take whole 4x4 blocks
+        let data = TextureData::from_dds(&file).unwrap();
+        assert_eq!(data.format, vk::Format::BC1_RGBA_SRGB_BLOCK);
+        assert_eq!(data.extent, vk::Extent2D { width: 8, height: 8 });
+        let sizes: Vec<usize> = data.levels.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [32, 8, 8, 8]);
+        assert_eq!(data.levels[1], (32..40).collect::<Vec<u8>>());
+
+        let mut dds = ddsfile::Dds::new_d3d(ddsfile::NewD3dParams {
+            height: 4,
+            width: 4,
+            depth: None,
+            format: ddsfile::D3DFormat::DXT5,
+            mipmap_levels: None,
+            caps2: None
+        }).unwrap();
+        dds.data.fill(7);
+        let mut file = vec![];
+        dds.write(&mut file).unwrap();
+
+        let data = TextureData::from_dds(&file).unwrap();
+        assert_eq!(data.format, vk::Format::BC3_UNORM_BLOCK);
+        assert_eq!(data.levels, vec![vec![7; 16]]);
+    }
+
+    #[test]
+    fn dds_formats_without_a_vulkan_match_are_rejected() {
+        let dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height: 4,
+            width: 4,
+            depth: None,
+            format: ddsfile::DxgiFormat::R32G32B32_Float,
+            mipmap_levels: None,
+            array_layers: None,
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+            alpha_mode: ddsfile::AlphaMode::Unknown
+        }).unwrap();
+        let mut file = vec![];
+        dds.write(&mut file).unwrap();
+
+        assert!(TextureData::from_dds(&file).is_err());
+    }
+
+    #[test]
+    fn basis_files_describe_their_slices() {
+        let slices = [
+            BasisSlice { extent: vk::Extent2D { width: 8, height: 4 }, level: 0, alpha: false, data: vec![1; 32] },
+            BasisSlice { extent: vk::Extent2D { width: 4, height: 2 }, level: 1, alpha: false, data: vec![2; 16] },
+        ];
+        let file = basis_file(0, None, &slices);
+
+        let slices_offset = BASIS_HEADER_SIZE + 2 * BASIS_SLICE_SIZE;
+        assert_eq!(file.len(), slices_offset + 48);
+        assert_eq!(get(&file, 0, 2), 0x4273);
+        assert_eq!(get(&file, 8, 4), file.len() - BASIS_HEADER_SIZE);
+        assert_eq!(get(&file, 14, 3), 2);
+        assert_eq!(get(&file, 17, 3), 1);
+        assert_eq!(get(&file, 20, 1), 1);
+        assert_eq!(get(&file, 65, 4), BASIS_HEADER_SIZE);
+
+        let second = BASIS_HEADER_SIZE + BASIS_SLICE_SIZE;
+        assert_eq!(get(&file, second + 3, 1), 1);
+        assert_eq!((get(&file, second + 5, 2), get(&file, second + 7, 2)), (4, 2));
+        assert_eq!((get(&file, second + 9, 2), get(&file, second + 11, 2)), (1, 1));
+        assert_eq!(get(&file, second + 13, 4), slices_offset + 32);
+        assert_eq!(get(&file, second + 17, 4), 16);
+        assert_eq!(file[slices_offset + 32..], [2; 16]);
+
+        let transcoder = Transcoder::new();
+        assert!(transcoder.validate_header(&file));
+        assert_eq!(transcoder.image_count(&file), 1);
+        assert_eq!(transcoder.image_level_count(&file, 0), 2);
+        let description = transcoder.image_level_description(&file, 0, 1).unwrap();
+        assert_eq!((description.original_width, description.original_height), (4, 2));
+    }
+
+    #[test]
+    fn basis_codebooks_go_between_descriptions_and_slices() {
+        let data = [1, 2, 3, 4, 5, 6];
+        let codebooks = BasisCodebooks { endpoint_count: 9, selector_count: 7, lengths: [1, 2, 3], data: &data };
+        let slices = [BasisSlice { extent: vk::Extent2D { width: 4, height: 4 }, level: 0, alpha: false, data: vec![0; 8] }];
+        let file = basis_file(BASIS_FLAG_ETC1S, Some(&codebooks), &slices);
+
+        let codebooks_offset = BASIS_HEADER_SIZE + BASIS_SLICE_SIZE;
+        assert_eq!(get(&file, 20, 1), 0);
+        assert_eq!(get(&file, 21, 2), BASIS_FLAG_ETC1S as usize);
+        assert_eq!((get(&file, 39, 2), get(&file, 41, 4), get(&file, 45, 3)), (9, codebooks_offset, 1));
+        assert_eq!((get(&file, 48, 2), get(&file, 50, 4), get(&file, 54, 3)), (7, codebooks_offset + 1, 2));
+        assert_eq!((get(&file, 57, 4), get(&file, 61, 4)), (codebooks_offset + 3, 3));
+        assert_eq!(file[codebooks_offset..codebooks_offset + 6], data);
+        assert_eq!(get(&file, BASIS_HEADER_SIZE + 13, 4), codebooks_offset + 6);
+    }
+
+    #[test]
+    fn repacked_uastc_transcodes_like_the_encoded_file() {
+        let encoded = compress(BasisTextureFormat::UASTC4x4);
+        let expected = TextureData::parse_basis(&encoded, &|_| false).unwrap();
+        assert_eq!(expected.levels.len(), 4);
+
+        let levels: Vec<Vec<u8>> = basis_slices(&encoded).into_iter().map(|slice| slice.data).collect();
+        let file = ktx2_file(0, 0, UASTC, LINEAR, (8, 8), &levels);
+
+        //nothing supported falls back to rgba
+        let data = TextureData::parse_ktx2(&file, &|_| false).unwrap();
+        assert_eq!(data.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(data.extent, vk::Extent2D { width: 8, height: 8 });
+        assert_eq!(data.levels, expected.levels);
+
+        let file = ktx2_file(0, 0, UASTC, SRGB, (8, 8), &levels);
+        let data = TextureData::parse_ktx2(&file, &|format| format == vk::Format::ASTC_4X4_UNORM_BLOCK).unwrap();
+        assert_eq!(data.format, vk::Format::ASTC_4X4_SRGB_BLOCK);
+    }
+
+    #[test]
+    fn repacked_etc1s_transcodes_like_the_encoded_file() {
+        let encoded = compress(BasisTextureFormat::ETC1S);
+        let expected = TextureData::parse_basis(&encoded, &|_| false).unwrap();
+
+        let lengths = [get(&encoded, 45, 3), get(&encoded, 54, 3), get(&encoded, 61, 4)];
+        let mut data = vec![];
+        for (offset, length) in [41, 50, 57].into_iter().zip(lengths) {
+            let offset = get(&encoded, offset, 4);
+            data.extend_from_slice(&encoded[offset..offset + length]);
+        }
+        let codebooks = BasisCodebooks {
+            endpoint_count: get(&encoded, 39, 2) as u16,
+            selector_count: get(&encoded, 48, 2) as u16,
+            lengths: lengths.map(|length| length as u32),
+            data: &data
+        };
+        let file = basis_file(BASIS_FLAG_ETC1S, Some(&codebooks), &basis_slices(&encoded));
+
+        let data = TextureData::parse_basis(&file, &|_| false).unwrap();
+        assert_eq!(data.levels, expected.levels);
+    }
+
+    #[test]
+    fn unsupported_formats_are_decoded_to_bgra8() {
+        //a red bc1 block, every texel takes the first endpoint
+        let block = vec![0x00, 0xf8, 0x1f, 0x00, 0, 0, 0, 0];
+        let bc1 = |format| TextureData {
+            format,
+            extent: vk::Extent2D { width: 4, height: 4 },
+            levels: vec![block.clone()]
+        };
+
+        let data = bc1(vk::Format::BC1_RGB_UNORM_BLOCK).or_decoded(|_| false).unwrap();
+        assert_eq!(data.format, vk::Format::B8G8R8A8_UNORM);
+        assert_eq!(data.levels, vec![[0, 0, 255, 255].repeat(16)]);
+
+        let data = bc1(vk::Format::BC1_RGB_SRGB_BLOCK).or_decoded(|_| false).unwrap();
+        assert_eq!(data.format, vk::Format::B8G8R8A8_SRGB);
+
+        let data = bc1(vk::Format::BC1_RGB_UNORM_BLOCK).or_decoded(|_| true).unwrap();
+        assert_eq!(data.format, vk::Format::BC1_RGB_UNORM_BLOCK);
+        assert_eq!(data.levels, vec![block]);
+    }
+}
@@ -0,0 +1,95 @@
+use ash::vk;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//owned by the device, which destroys it
+pub struct Timeline {
+    pub semaphore: vk::Semaphore,
+    value: AtomicU64
+}
+
+impl Timeline {
+    pub fn new(logical: &ash::Device) -> Self {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+
+        let info = vk::SemaphoreCreateInfo::builder()
+            .push_next(&mut type_info);
+
+        let semaphore = unsafe {
+            logical.create_semaphore(&info, None).unwrap()
+        };
+
+        Self {
+            semaphore,
+            value: AtomicU64::new(0)
+        }
+    }
+
+    //value the next submission on this timeline signals
+    pub fn next_value(&self) -> u64 {
+        self.value.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn last_submitted(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn completed(&self, logical: &ash::Device) -> u64 {
+        unsafe {
+            logical.get_semaphore_counter_value(self.semaphore).unwrap()
+        }
+    }
+
+    pub fn is_complete(&self, logical: &ash::Device, value: u64) -> bool {
+        self.completed(logical) >= value
+    }
+
+    pub fn wait(&self, logical: &ash::Device, value: u64) {
+        let semaphores = [self.semaphore];
+        let values = [value];
+
+        let info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(&semaphores)
+            .values(&values);
+
+        unsafe {
+            logical.wait_semaphores(&info, u64::MAX).unwrap();
+        }
+    }
+
+    pub fn wait_idle(&self, logical: &ash::Device) {
+        self.wait(logical, self.last_submitted());
+    }
+
+    //only the device destroys its timelines, once nothing waits on them anymore
+    pub(crate) unsafe fn cleanup(&mut self, logical: &ash::Device) {
+        logical.destroy_semaphore(self.semaphore, None);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SemaphoreWait {
+    pub semaphore: vk::Semaphore,
+    //ignored for binary semaphores
+    pub value: u64,
+    pub stage: vk::PipelineStageFlags
+}
+
+impl SemaphoreWait {
+    pub fn binary(semaphore: vk::Semaphore, stage: vk::PipelineStageFlags) -> Self {
+        Self {
+            semaphore,
+            value: 0,
+            stage
+        }
+    }
+
+    pub fn timeline(timeline: &Timeline, value: u64, stage: vk::PipelineStageFlags) -> Self {
+        Self {
+            semaphore: timeline.semaphore,
+            value,
+            stage
+        }
+    }
+}
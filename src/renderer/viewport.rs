@@ -0,0 +1,420 @@
+use super::{Device, Frame};
+use super::window::Window;
+use super::config::RendererConfig;
+use super::swapchain::Swapchain;
+use super::pipeline::Pipeline;
+use super::sync::{self, ResourceState};
+use super::graph::{RenderGraph, ImageHandle, PassContext};
+use super::profiler::GpuProfiler;
+use super::stats::FrameStats;
+use super::timeline::SemaphoreWait;
+use super::deletion::{DeletionQueue, Retired};
+
+use ash::vk;
+use std::{sync::Arc, time::Instant};
+
+//a window with everything needed to present to it, the pipelines and the device are shared between viewports
+pub struct Viewport {
+    pub device: Arc<Device>,
+    pub swapchain: Swapchain,
+    pub graph: RenderGraph,
+    pub backbuffer: ImageHandle,
+    pub depth: ImageHandle,
+    //allocated from the renderer's pool, which frees them
+    pub command_buffers: Vec<vk::CommandBuffer>,
+    pub profiler: GpuProfiler,
+    pub stats: FrameStats,
+    //set on resizes and fullscreen changes, the next frame recreates the swapchain first
+    pub swapchain_outdated: bool,
+    //dropped last, the surface and the swapchain are built on it
+    pub window: Window
+}
+
+impl Viewport {
+    pub fn new(
+        device: &Arc<Device>,
+        window: Window,
+        render_pass: Option<vk::RenderPass>,
+        pipeline: &Pipeline,
+        command_pool: vk::CommandPool,
+        title: &str,
+        config: &RendererConfig)
+    -> Self {
+        let swapchain = Swapchain::new(device, &window, render_pass, config.present_mode.to_vk());
+
+        let (graph, backbuffer, depth) = Self::new_graph(device, &swapchain, pipeline, render_pass);
+
+        let profiler = GpuProfiler::new(device, swapchain.image_count, config.profiler.max_scopes, config.profiler.pipeline_statistics);
+
+        let command_buffers = Self::new_command_buffers(device, &swapchain, command_pool, title);
+
+        let stats = FrameStats::new(config.stats.window, config.stats.display, title);
+
+        Self {
+            device: device.clone(),
+            swapchain,
+            graph,
+            backbuffer,
+            depth,
+            command_buffers,
+            profiler,
+            stats,
+            swapchain_outdated: false,
+            window
+        }
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        let size = self.window.handle.inner_size();
+        size.width == 0 || size.height == 0
+    }
+
+    //returns false while minimized, the replaced resources are retired once the viewport's frames are done with them
+    pub fn recreate_swapchain(
+        &mut self,
+        render_pass: Option<vk::RenderPass>,
+        pipeline: &Pipeline,
+        command_pool: vk::CommandPool,
+        deletion_queue: &mut DeletionQueue)
+    -> bool {
+        if self.is_minimized() {
+            return false;
+        }
+
+        let device = self.device.clone();
+        let last_submitted = self.last_submitted();
+        let mut retire = |resource| deletion_queue.push_at(&device.graphics_timeline, last_submitted, resource);
+
+        let mut swapchain = self.swapchain.recreate(&self.window, render_pass);
+        let image_count = self.swapchain.image_count;
+
+        //per image resources like the profiler's queries are reused, so frames still wait on the old frames with their index
+        let kept = image_count.min(swapchain.image_count);
+        swapchain.frame_values[..kept].copy_from_slice(&self.swapchain.frame_values[..kept]);
+        swapchain.image_values[..kept].copy_from_slice(&self.swapchain.image_values[..kept]);
+        retire(Retired::Swapchain(std::mem::replace(&mut self.swapchain, swapchain)));
+
+        let (graph, backbuffer, depth) = Self::new_graph(&self.device, &self.swapchain, pipeline, render_pass);
+        retire(Retired::RenderGraph(std::mem::replace(&mut self.graph, graph)));
+        self.backbuffer = backbuffer;
+        self.depth = depth;
+
+        //the profiler has a slot per swapchain image
+        if image_count != self.swapchain.image_count {
+            let profiler = GpuProfiler::new(
+                &self.device,
+                self.swapchain.image_count,
+                self.profiler.max_scopes,
+                self.profiler.statistics_pool.is_some());
+            retire(Retired::Profiler(std::mem::replace(&mut self.profiler, profiler)));
+        }
+
+        let command_buffers = Self::new_command_buffers(&self.device, &self.swapchain, command_pool, &self.stats.title);
+        retire(Retired::CommandBuffers(command_pool, std::mem::replace(&mut self.command_buffers, command_buffers)));
+
+        self.swapchain_outdated = false;
+        true
+    }
+
+    //graphics timeline value of the viewport's last submission
+    pub fn last_submitted(&self) -> u64 {
+        self.swapchain.frame_values.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        self.window.toggle_fullscreen();
+        self.swapchain_outdated = true;
+    }
+
+    pub fn begin_frame(&mut self) -> Option<Frame> {
+        self.stats.begin_frame();
+
+        // the frame slot's semaphores are free once its last submission finished:
+        let slot = (self.swapchain.current_image + 1) % self.swapchain.image_count;
+        self.swapchain.current_image = slot;
+
+        let wait_start = Instant::now();
+        self.device.graphics_timeline.wait(&self.device.logical, self.swapchain.frame_values[slot]);
+        let fence_wait = wait_start.elapsed();
+
+        let acquire_start = Instant::now();
+        let acquired = unsafe {
+            self.swapchain.loader.acquire_next_image(
+                self.swapchain.swapchain,
+                u64::MAX,
+                self.swapchain.image_available_semaphores[slot],
+                vk::Fence::null(),
+            )
+        };
+        let (image_index, suboptimal) = match acquired {
+            Ok(acquired) => acquired,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.swapchain_outdated = true;
+                return None;
+            },
+            Err(error) => panic!("failed to acquire a swapchain image: {}", error),
+        };
+        self.swapchain_outdated |= suboptimal;
+        self.stats.acquire_wait.push(acquire_start.elapsed());
+        let image_index = image_index as usize;
+
+        // the image's command buffer may still be in flight from another frame slot:
+        let wait_start = Instant::now();
+        self.device.graphics_timeline.wait(&self.device.logical, self.swapchain.image_values[image_index]);
+        self.stats.fence_wait.push(fence_wait + wait_start.elapsed());
+
+        // reading back the gpu timings of its last submission:
+        self.profiler.collect(image_index);
+
+        Some(Frame {
+            window: self.window.handle.id(),
+            image_index,
+            slot,
+            command_buffer: self.command_buffers[image_index]
+        })
+    }
+
+    //records the frame's command buffer, app is called from the passes drawing into the backbuffer
+    pub fn end_frame(&mut self, frame: Frame, app: &dyn Fn(&PassContext)) {
+        self.record(&frame, app);
+
+        // submit:
+        let waits = [SemaphoreWait::binary(
+            self.swapchain.image_available_semaphores[frame.slot],
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        )];
+        let semaphores_finished = [self.swapchain.render_finished_semaphores[frame.slot]];
+        let command_buffers = [frame.command_buffer];
+
+        let value = self.device.submit_graphics(&command_buffers, &waits, &semaphores_finished);
+        self.swapchain.frame_values[frame.slot] = value;
+        self.swapchain.image_values[frame.image_index] = value;
+        self.profiler.submitted(frame.image_index);
+
+        // present:
+        let swapchains = [self.swapchain.swapchain];
+        let indices = [frame.image_index as u32];
+
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&semaphores_finished)
+            .swapchains(&swapchains)
+            .image_indices(&indices);
+
+        let presented = unsafe {
+            self.swapchain.loader.queue_present(self.device.graphics_family.queues[0], &present_info)
+        };
+        match presented {
+            Ok(suboptimal) => self.swapchain_outdated |= suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.swapchain_outdated = true,
+            Err(error) => panic!("failed to present: {}", error),
+        }
+
+        self.stats.end_frame();
+        self.stats.report(&self.window.handle);
+    }
+
+    fn new_graph(
+        device: &Arc<Device>,
+        swapchain: &Swapchain,
+        pipeline: &Pipeline,
+        render_pass: Option<vk::RenderPass>)
+    -> (RenderGraph, ImageHandle, ImageHandle) {
+        let mut graph = RenderGraph::new();
+
+        let backbuffer = graph.import_image(
+            "backbuffer",
+            sync::color_range(1),
+            ResourceState::ACQUIRED,
+            Some(ResourceState::PRESENT));
+
+        //begin_frame waited for the image's last frame, which was the last one to use its depth buffer
+        let depth = graph.import_image(
+            "depth",
+            sync::depth_range(),
+            ResourceState::UNDEFINED,
+            None);
+
+        Self::add_main_pass(&mut graph, backbuffer, depth, swapchain, pipeline, render_pass);
+
+        graph.compile(device);
+
+        (graph, backbuffer, depth)
+    }
+
+
+    fn add_main_pass(
+        graph: &mut RenderGraph,
+        backbuffer: ImageHandle,
+        depth: ImageHandle,
+        swapchain: &Swapchain,
+        pipeline: &Pipeline,
+        render_pass: Option<vk::RenderPass>)
+    {
+        let framebuffers = swapchain.framebuffers.clone();
+        let extent = swapchain.extent;
+        let graphics = pipeline.graphics;
+
+        graph.add_pass("main", |pass| {
+            pass.write_image(backbuffer, ResourceState::COLOR_ATTACHMENT);
+            pass.write_image(depth, ResourceState::DEPTH_ATTACHMENT);
+        }, move |ctx| {
+            let device = ctx.device;
+
+            let clear_values = [
+                vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [0.0, 0.0, 0.0, 1.0],
+                    }
+                },
+                //depth is reversed, far away is 0
+                vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: 0.0,
+                        stencil: 0
+                    }
+                },
+            ];
+
+            let render_area = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            };
+
+            match render_pass {
+                Some(render_pass) => {
+                    let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+                        .render_pass(render_pass)
+                        .framebuffer(framebuffers[ctx.frame_index])
+                        .render_area(render_area)
+                        .clear_values(&clear_values);
+
+                    unsafe {
+                        device.logical.cmd_begin_render_pass(
+                            ctx.command_buffer,
+                            &render_pass_begin_info,
+                            vk::SubpassContents::INLINE);
+                    }
+                },
+                None => Self::begin_rendering(
+                    device,
+                    ctx.command_buffer,
+                    [ctx.view(backbuffer), ctx.view(depth)],
+                    render_area,
+                    clear_values),
+            }
+
+            unsafe {
+                device.logical.cmd_bind_pipeline(
+                    ctx.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    graphics
+                );
+
+                let viewports = [vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0
+                }];
+                device.logical.cmd_set_viewport(ctx.command_buffer, 0, &viewports);
+                device.logical.cmd_set_scissor(ctx.command_buffer, 0, &[render_area]);
+
+                device.logical.cmd_draw(ctx.command_buffer, 3, 1, 0, 0);
+            }
+
+            ctx.record_app();
+
+            match render_pass {
+                Some(_) => unsafe {
+                    device.logical.cmd_end_render_pass(ctx.command_buffer);
+                },
+                None => unsafe {
+                    device.dynamic_rendering.as_ref().unwrap().cmd_end_rendering(ctx.command_buffer);
+                },
+            }
+        });
+    }
+
+    //one per swapchain image, recorded again every frame
+    fn new_command_buffers(device: &Device, swapchain: &Swapchain, pool: vk::CommandPool, label: &str) -> Vec<vk::CommandBuffer> {
+        let alloc_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(pool)
+            .command_buffer_count(swapchain.image_count as u32);
+
+        let command_buffers = unsafe {
+            device.logical.allocate_command_buffers(&alloc_info).unwrap()
+        };
+
+        for (i, &command_buffer) in command_buffers.iter().enumerate() {
+            device.set_object_name(command_buffer, &format!("{} frame {}", label, i));
+        }
+        command_buffers
+    }
+
+    //begin_frame waited for the image's last submission, so its command buffer is free to reset
+    fn record(&mut self, frame: &Frame, app: &dyn Fn(&PassContext)) {
+        let device = &self.device;
+        let command_buffer = frame.command_buffer;
+        let i = frame.image_index;
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            device.logical.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty()).unwrap();
+            device.logical.begin_command_buffer(command_buffer, &begin_info).unwrap();
+        }
+        device.begin_label(command_buffer, &format!("{} frame {}", self.stats.title, i));
+
+        self.profiler.begin_frame(command_buffer, i);
+
+        self.graph.set_image(self.backbuffer, self.swapchain.images[i], self.swapchain.image_views[i]);
+        self.graph.set_image(self.depth, self.swapchain.depth_images[i].0, self.swapchain.depth_views[i]);
+        self.graph.execute(command_buffer, i, Some(&mut self.profiler), Some(app));
+
+        self.profiler.end_frame(command_buffer, i);
+
+        device.end_label(command_buffer);
+
+        unsafe {
+            device.logical.end_command_buffer(command_buffer).unwrap();
+        }
+    }
+
+    fn begin_rendering(
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        [color_view, depth_view]: [vk::ImageView; 2],
+        render_area: vk::Rect2D,
+        [color_clear, depth_clear]: [vk::ClearValue; 2])
+    {
+        let color_attachments = [
+            vk::RenderingAttachmentInfo::builder()
+                .image_view(color_view)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(color_clear)
+                .build()
+        ];
+
+        let depth_attachment = vk::RenderingAttachmentInfo::builder()
+            .image_view(depth_view)
+            .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .clear_value(depth_clear);
+
+        let rendering_info = vk::RenderingInfo::builder()
+            .render_area(render_area)
+            .layer_count(1)
+            .color_attachments(&color_attachments)
+            .depth_attachment(&depth_attachment);
+
+        unsafe {
+            device.dynamic_rendering.as_ref().unwrap().cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+}
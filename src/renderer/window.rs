@@ -1,61 +1,208 @@
+use super::instance::Instance;
+use super::config::{WindowConfig, WindowGeometry, FullscreenMode, VideoModeConfig};
+
 use ash::{vk, extensions::khr};
-use anyhow::Result;
+use std::sync::Arc;
+use winit::{dpi, monitor::MonitorHandle, window::Fullscreen};
+
+pub struct Surface {
+    pub instance: Arc<Instance>,
+    pub surface: vk::SurfaceKHR,
+    pub loader: khr::Surface
+}
+
+impl Surface {
+    pub fn new(instance: &Arc<Instance>, handle: &winit::window::Window) -> Self {
+        let surface = unsafe {
+            ash_window::create_surface(&instance.entry, &instance.handle, handle, None).unwrap()
+        };
+        let loader = khr::Surface::new(&instance.entry, &instance.handle);
+
+        Self {
+            instance: instance.clone(),
+            surface,
+            loader
+        }
+    }
+}
+
+//swapchains hold an Arc to their surface, so it outlives them
+impl Drop for Surface {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_surface(self.surface, None);
+        }
+    }
+}
 
 pub struct Window {
-    pub event_loop: Option<winit::event_loop::EventLoop<()>>,
+    //declared before the native window, which has to outlive it
+    pub surface: Arc<Surface>,
+
     pub handle: winit::window::Window,
 
-    pub surface: vk::SurfaceKHR,
-    pub surface_loader: khr::Surface,
-    
-    pub format: vk::SurfaceFormatKHR
+    pub format: vk::SurfaceFormatKHR,
+
+    pub fullscreen: FullscreenMode,
+    pub fullscreen_toggle: FullscreenMode,
+    pub monitor: Option<usize>,
+    pub video_mode: Option<VideoModeConfig>
 }
 
 impl Window {
-    pub fn new_handle() -> (winit::event_loop::EventLoop<()>, winit::window::Window) {
-        let event_loop = winit::event_loop::EventLoop::new();
-        let handle = winit::window::Window::new(&event_loop).unwrap();
+    pub fn new_handle(event_loop: &winit::event_loop::EventLoopWindowTarget<()>, config: &WindowConfig) -> winit::window::Window {
+        let fullscreen = resolve_fullscreen(
+            config.fullscreen,
+            config.monitor,
+            config.video_mode,
+            event_loop.available_monitors().collect(),
+            event_loop.primary_monitor());
 
-        (event_loop, handle)
+        let mut builder = winit::window::WindowBuilder::new()
+            .with_title(&config.title)
+            .with_inner_size(dpi::LogicalSize::new(config.width, config.height))
+            .with_resizable(config.resizable)
+            .with_maximized(config.maximized)
+            .with_fullscreen(fullscreen);
+        if let Some([x, y]) = config.position {
+            builder = builder.with_position(dpi::PhysicalPosition::new(x, y));
+        }
+        builder.build(event_loop).unwrap()
     }
-    
-    pub fn new(event_loop: winit::event_loop::EventLoop<()>,
-        handle: winit::window::Window,
+
+    //pipelines are shared with other windows, so with a preferred format the surface has to support it
+    pub fn new(handle: winit::window::Window,
+        config: &WindowConfig,
         physical_device: vk::PhysicalDevice,
-        entry: &ash::Entry,
-        instance: &ash::Instance) -> Self {
-        let surface = unsafe {
-            ash_window::create_surface(entry, instance, &handle, None).unwrap()
-        };
-        let surface_loader = khr::Surface::new(entry, instance);
+        instance: &Arc<Instance>,
+        preferred_format: Option<vk::SurfaceFormatKHR>) -> anyhow::Result<Self> {
+        let surface = Arc::new(Surface::new(instance, &handle));
 
-        let format = unsafe {
-            surface_loader.get_physical_device_surface_formats(physical_device, surface).unwrap()[0]
+        let formats = unsafe {
+            surface.loader.get_physical_device_surface_formats(physical_device, surface.surface).unwrap()
+        };
+        let format = match preferred_format {
+            Some(preferred) if formats.contains(&preferred) => preferred,
+            Some(preferred) => anyhow::bail!("window {:?} doesn't support the shared surface format {:?}", config.title, preferred.format),
+            None => formats[0],
         };
 
-        Self {
-            event_loop: Some(event_loop),
-            handle,
+        Ok(Self {
             surface,
-            surface_loader,
-            format
+            handle,
+            format,
+            fullscreen: config.fullscreen,
+            fullscreen_toggle: config.fullscreen_toggle,
+            monitor: config.monitor,
+            video_mode: config.video_mode
+        })
+    }
+
+    //the swapchain has to be recreated afterwards
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode) {
+        let fullscreen = resolve_fullscreen(
+            mode,
+            self.monitor,
+            self.video_mode,
+            self.handle.available_monitors().collect(),
+            self.handle.primary_monitor());
+
+        self.fullscreen = match fullscreen {
+            None => FullscreenMode::Windowed,
+            Some(Fullscreen::Borderless(_)) => FullscreenMode::Borderless,
+            Some(Fullscreen::Exclusive(_)) => FullscreenMode::Exclusive,
+        };
+        self.handle.set_fullscreen(fullscreen);
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        match self.fullscreen {
+            FullscreenMode::Windowed => self.set_fullscreen(self.fullscreen_toggle),
+            _ => self.set_fullscreen(FullscreenMode::Windowed),
+        }
+    }
+
+    //the windowed size is only known while windowed, so fullscreen keeps the remembered one
+    pub fn geometry(&self, remembered: &WindowConfig) -> WindowGeometry {
+        let windowed = self.fullscreen == FullscreenMode::Windowed && !self.handle.is_maximized();
+        let size = self.handle.inner_size().to_logical::<u32>(self.handle.scale_factor());
+
+        WindowGeometry {
+            position: if windowed {
+                self.handle.outer_position().ok().map(|position| [position.x, position.y])
+            } else {
+                remembered.position
+            },
+            width: if windowed { size.width } else { remembered.width },
+            height: if windowed { size.height } else { remembered.height },
+            maximized: self.handle.is_maximized(),
+            fullscreen: self.fullscreen,
+            monitor: self.current_monitor_index().or(self.monitor)
         }
     }
 
+    fn current_monitor_index(&self) -> Option<usize> {
+        let current = self.handle.current_monitor()?;
+        self.handle.available_monitors().position(|monitor| monitor == current)
+    }
+
     pub fn surface_capabilities(&self, physical_device: vk::PhysicalDevice) -> vk::SurfaceCapabilitiesKHR {
         unsafe {
-            self.surface_loader.get_physical_device_surface_capabilities(physical_device, self.surface).unwrap()
+            self.surface.loader.get_physical_device_surface_capabilities(physical_device, self.surface.surface).unwrap()
+        }
+    }
+
+    pub fn present_modes(&self, physical_device: vk::PhysicalDevice) -> Vec<vk::PresentModeKHR> {
+        unsafe {
+            self.surface.loader.get_physical_device_surface_present_modes(physical_device, self.surface.surface).unwrap()
         }
     }
 
-    pub fn event_loop(&mut self) -> Result<winit::event_loop::EventLoop<()>> {
-        match self.event_loop.take() {
-            None => anyhow::bail!("EventLoop was acquired before"),
-            Some(el) => Ok(el)
+    pub fn supports_present(&self, physical_device: vk::PhysicalDevice, queue_family: u32) -> bool {
+        unsafe {
+            self.surface.loader.get_physical_device_surface_support(physical_device, queue_family, self.surface.surface).unwrap_or(false)
         }
     }
+}
+
+fn resolve_fullscreen(
+    mode: FullscreenMode,
+    monitor: Option<usize>,
+    video_mode: Option<VideoModeConfig>,
+    monitors: Vec<MonitorHandle>,
+    primary: Option<MonitorHandle>)
+-> Option<Fullscreen> {
+    let monitor = match monitor {
+        Some(index) if index < monitors.len() => Some(monitors[index].clone()),
+        Some(index) => {
+            log::warn!("monitor {} does not exist, using the primary one", index);
+            primary
+        },
+        None => primary,
+    };
 
-    pub unsafe fn cleanup(&mut self) {
-        self.surface_loader.destroy_surface(self.surface, None);
+    match mode {
+        FullscreenMode::Windowed => None,
+        FullscreenMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        FullscreenMode::Exclusive => {
+            let modes: Vec<_> = monitor.iter().flat_map(|monitor| monitor.video_modes()).collect();
+
+            let chosen = match video_mode {
+                Some(wanted) => modes.iter()
+                    .filter(|mode| mode.size() == dpi::PhysicalSize::new(wanted.width, wanted.height))
+                    .filter(|mode| wanted.refresh_rate == 0 || mode.refresh_rate() == wanted.refresh_rate)
+                    .max_by_key(|mode| (mode.refresh_rate(), mode.bit_depth())),
+                None => modes.iter()
+                    .max_by_key(|mode| (mode.size().width * mode.size().height, mode.refresh_rate(), mode.bit_depth())),
+            };
+
+            match chosen {
+                Some(chosen) => Some(Fullscreen::Exclusive(chosen.clone())),
+                None => {
+                    log::warn!("no matching video mode for exclusive fullscreen, using borderless");
+                    Some(Fullscreen::Borderless(monitor))
+                },
+            }
+        },
     }
-}
\ No newline at end of file
+}